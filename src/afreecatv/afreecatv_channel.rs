@@ -1,47 +1,37 @@
-use crate::{afreecatv_video::AfreecaVideo, tools::CLIENT};
-use serde_json::Value;
+use crate::{
+    afreecatv_models::VodsPage,
+    afreecatv_video::AfreecaVideo,
+    error::Result,
+    http::{endpoints, HttpRequest},
+    source::ChannelSource,
+    tools::send_json,
+};
 use std::thread::{spawn, JoinHandle};
 
 #[derive(Clone)]
-pub(crate) struct Blog {
+pub struct Blog {
     user_id: String,
 }
 
 impl Blog {
-    pub(crate) fn new<S: AsRef<str>>(user_id: S) -> Blog {
+    pub fn new<S: AsRef<str>>(user_id: S) -> Blog {
         Blog {
             user_id: user_id.as_ref().to_owned(),
         }
     }
-    pub(crate) fn videos(self) -> Vec<AfreecaVideo> {
+    pub fn videos(&self) -> Result<Vec<AfreecaVideo>> {
         let vod_list_url = format!(
-            "https://bjapi.afreecatv.com/api/{}/vods/all?per_page=60",
+            "{}/api/{}/vods/all?per_page=60",
+            endpoints().afreeca_bjapi,
             self.user_id
         );
-        let vod_list_xml: Value = CLIENT
-            .get(vod_list_url)
-            .header("Connection", "keep-alive")
-            .send()
-            .expect("https://bjapi.afreecatv.com refused to connect")
-            .json()
-            .unwrap();
-        let limit = vod_list_xml
-            .get("meta")
-            .unwrap()
-            .get("last_page")
-            .unwrap()
-            .as_u64()
-            .unwrap();
-        let size = vod_list_xml
-            .get("meta")
-            .unwrap()
-            .get("total")
-            .unwrap()
-            .as_u64()
-            .unwrap();
+        let vod_list: VodsPage =
+            send_json(HttpRequest::get(vod_list_url).header("Connection", "keep-alive"))?;
+        let limit = vod_list.meta.last_page;
+        let size = vod_list.meta.total;
         let mut videos: Vec<AfreecaVideo> = Vec::with_capacity(size as usize);
 
-        let mut page_chunks: Vec<JoinHandle<Vec<AfreecaVideo>>> =
+        let mut page_chunks: Vec<JoinHandle<Result<Vec<AfreecaVideo>>>> =
             Vec::with_capacity(limit as usize);
 
         for i in 1..=limit {
@@ -50,27 +40,38 @@ impl Blog {
             page_chunks.push(retrieval_thread)
         }
         for page_chunk in page_chunks {
-            let mut thread_videos = page_chunk.join().unwrap();
+            let mut thread_videos = page_chunk.join().unwrap()?;
             videos.append(&mut thread_videos)
         }
-        videos
+        Ok(videos)
     }
 
-    fn load_videos_chunk(&self, i: u64) -> Vec<AfreecaVideo> {
+    fn load_videos_chunk(&self, i: u64) -> Result<Vec<AfreecaVideo>> {
         let mut videos: Vec<AfreecaVideo> = Vec::with_capacity(60);
         let vod_list_url = format!(
-            "https://bjapi.afreecatv.com/api/{}/vods/all?page={}&per_page=60",
-            self.user_id, i
+            "{}/api/{}/vods/all?page={}&per_page=60",
+            endpoints().afreeca_bjapi,
+            self.user_id,
+            i
         );
-        let vod_list_xml: Value = CLIENT.get(vod_list_url).send().unwrap().json().unwrap();
-        let vods = vod_list_xml.get("data").unwrap().as_array().unwrap();
-        for vod in vods {
-            let title_no = vod.get("title_no").unwrap().to_string();
-            let station_no = vod.get("station_no").unwrap().to_string();
-            let bbs_no = vod.get("bbs_no").unwrap().to_string();
-            let video = AfreecaVideo::new_unchecked(title_no, station_no, bbs_no);
+        let vod_list: VodsPage = send_json(HttpRequest::get(vod_list_url))?;
+        for vod in vod_list.data {
+            let video = AfreecaVideo::new_unchecked(
+                vod.title_name,
+                vod.title_no,
+                vod.station_no,
+                vod.bbs_no,
+            );
             videos.push(video);
         }
-        videos
+        Ok(videos)
+    }
+}
+
+impl ChannelSource for Blog {
+    type Video = AfreecaVideo;
+
+    fn videos(&self) -> Result<Vec<AfreecaVideo>> {
+        Blog::videos(self)
     }
 }
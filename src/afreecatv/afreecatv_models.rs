@@ -0,0 +1,23 @@
+//! Typed responses of the AfreecaTV JSON APIs used by this crate
+use serde::Deserialize;
+
+/// A page of `https://bjapi.afreecatv.com/api/{user_id}/vods/all`
+#[derive(Deserialize, Debug)]
+pub struct VodsPage {
+    pub data: Vec<BlogVod>,
+    pub meta: VodsMeta,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BlogVod {
+    pub title_no: u32,
+    pub station_no: u32,
+    pub bbs_no: u32,
+    pub title_name: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VodsMeta {
+    pub last_page: u64,
+    pub total: u64,
+}
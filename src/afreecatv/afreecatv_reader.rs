@@ -1,12 +1,10 @@
 use crate::{
-    tools::{exit_error, get_input},
-    {afreecatv_channel::Blog, afreecatv_video::AfreecaVideo, tools::get_filter},
+    cli::{AfreecaCommand, DEFAULT_CONCURRENCY},
+    exit_error, export, get_filter, get_input, report, retry_once_then_skip, search,
 };
+use chat_reader::{source::print_videos, AfreecaVideo, Blog, Error, Result, VideoSource};
 
-use std::{
-    sync::mpsc::channel,
-    thread::spawn,
-};
+use std::thread::spawn;
 
 pub(crate) fn main() {
     loop {
@@ -30,10 +28,72 @@ pub(crate) fn main() {
     }
 }
 
+/// Runs an `afreeca` command given on the command line
+pub(crate) fn run(command: AfreecaCommand) -> Result<()> {
+    match command {
+        AfreecaCommand::Video {
+            url,
+            options,
+            export,
+        } => {
+            let query = options.prepare()?;
+            if let (Some(flag), Some(_)) = (options.report_flag(), export.export) {
+                return Err(Error::Parse(format!(
+                    "{} with --export, which cannot be used together",
+                    flag
+                )));
+            }
+            let video = AfreecaVideo::new(&url)?;
+            if let Some(format) = export.export {
+                return export::export(&video, &query, format, &export);
+            }
+            if options.stats {
+                return report::stats(&video, &query, options.trend_window, options.top);
+            }
+            if options.highlights.highlights {
+                let highlights = (&options.highlights).into();
+                return report::highlights(&video, &query, &highlights, options.top);
+            }
+            video.print_chat_blocking(&query)
+        }
+        AfreecaCommand::Blog { name, options } => {
+            let query = options.prepare()?;
+            let videos = Blog::new(&name).videos()?;
+            //the list is kept to index the chat archived while reading it
+            let listed = videos.to_owned();
+            if options.stats {
+                report::channel_stats(
+                    &name,
+                    videos,
+                    &query,
+                    options.trend_window,
+                    options.top,
+                    options.concurrency.into(),
+                )?
+            } else if options.highlights.highlights {
+                report::channel_highlights(
+                    videos,
+                    &query,
+                    &(&options.highlights).into(),
+                    options.top,
+                    options.concurrency.into(),
+                )?
+            } else {
+                print_videos(
+                    videos,
+                    &query,
+                    options.concurrency.into(),
+                    retry_once_then_skip(),
+                )?
+            }
+            search::sync_index(&name, &listed)
+        }
+    }
+}
+
 pub(crate) fn input_vod() {
     print!("Input VOD Link >>> ");
     let vod_link = get_input();
-    dbg!(&vod_link);
     let video_get_thread = spawn(move || AfreecaVideo::new(&vod_link));
     let filter = match get_filter() {
         Ok(filter) => filter,
@@ -43,7 +103,9 @@ pub(crate) fn input_vod() {
         Ok(video) => video,
         Err(e) => exit_error(e),
     };
-    video.print_chat_blocking(&filter);
+    if let Err(e) = video.print_chat_blocking(&filter.into()) {
+        exit_error(e)
+    }
 }
 
 pub(crate) fn input_blog() {
@@ -54,22 +116,16 @@ pub(crate) fn input_blog() {
         Ok(filter) => filter,
         Err(e) => exit_error(e),
     };
-    let videos = videos_get_thread.join().unwrap();
-    let mut threads = Vec::new();
-    for video in videos {
-        let (tx, rx) = channel();
-        let filter = filter.to_owned();
-        let video_thread = video.to_owned();
-        let thread = spawn(move || video_thread.print_chat(&filter, rx));
-        threads.push((video, tx, thread))
-    }
-    for reader in threads {
-        let video = reader.0;
-        let tx = reader.1;
-        let chat_thread = reader.2;
-
-        println!("\nWorking on: {}", video.title_no);
-        tx.send(());
-        chat_thread.join();
+    let videos = match videos_get_thread.join().unwrap() {
+        Ok(videos) => videos,
+        Err(e) => exit_error(e),
+    };
+    if let Err(e) = print_videos(
+        videos,
+        &filter.into(),
+        DEFAULT_CONCURRENCY.into(),
+        retry_once_then_skip(),
+    ) {
+        exit_error(e)
     }
 }
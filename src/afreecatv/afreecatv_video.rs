@@ -1,15 +1,22 @@
-use crate::tools::{exit_error, extract_digits, format_time, print_queue, CLIENT};
+use crate::{
+    chat::{ChatMessage, Platform},
+    error::{Error, Result},
+    http::{endpoints, HttpRequest},
+    source::{Resumable, VideoSource},
+    tools::{extract_digits, send},
+};
 use lazy_static::lazy_static;
 use regex::Regex;
-use reqwest::header::COOKIE;
 use roxmltree::{Document, Node};
-use std::num::ParseIntError;
-use std::sync::mpsc::{channel, Receiver};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::VecDeque;
 lazy_static! {
     //working on initial URL
     static ref TITLE_NO_MATCHER: Regex = Regex::new("STATION/[0-9]{8}").unwrap();
     static ref BBS_NO_MATCHER: Regex = Regex::new("nBbsNo=[0-9]{8}").unwrap();
     static ref STATION_NO_MATCHER: Regex = Regex::new("nStationNo=[0-9]{8}").unwrap();
+    static ref TITLE_MATCHER: Regex = Regex::new(r#"<meta property="og:title" content="([^"]*)""#).unwrap();
     //working on stbbs info page
     static ref ROW_KEY_MATCHER: Regex = Regex::new(r#"key=".*">"#).unwrap();
     static ref ROW_TIME_MATCHER: Regex = Regex::new(r#"file duration=".*" key"#).unwrap();
@@ -24,136 +31,390 @@ hESimjmpn4vuuyKPahezPgzUYwUI6aI40vce6AiWkFZDM6314tglYTo0fMpjqJBeAyBlmvEdT7_JGXCA
 -1rTCrwsP3m5a_vTK1UrHmAcRT3H8biTle_u6_pjf8Z0JGLjES_3rzTJ9YNH5UFcZ2FyA0nU2nPReG9wirYCspxG3FoZax7zYkhLcFJWy6j1cVpts2N_5kzybkwQvk03JPVGfS9o0ZP3EeqyRAJAY8g_OX;";
 
 #[derive(Clone)]
-pub(crate) struct AfreecaVideo {
-    pub(crate) title_no: u32,
+pub struct AfreecaVideo {
+    pub title: String,
+    pub title_no: u32,
     station_no: u32,
     bbs_no: u32,
 }
 
 impl AfreecaVideo {
-    pub(crate) fn new<S: AsRef<str>>(url: S) -> Result<AfreecaVideo, ParseIntError> {
-        let view_source = CLIENT.get(url.as_ref()).send().unwrap().text().unwrap();
-        let title_no = TITLE_NO_MATCHER
-            .find(url.as_ref())
-            .expect("Invalid URL!")
-            .as_str()[8..]
-            .parse()?;
-        let station_no = STATION_NO_MATCHER.find(&view_source).unwrap().as_str()[11..].parse()?;
-        let bbs_no = BBS_NO_MATCHER.find(&view_source).unwrap().as_str()[7..].parse()?;
+    pub fn new<S: AsRef<str>>(url: S) -> Result<AfreecaVideo> {
+        let title_no = match TITLE_NO_MATCHER.find(url.as_ref()) {
+            Some(title_no) => title_no.as_str()[8..].parse()?,
+            None => return Err(Error::Parse(format!("'{}' as a video URL", url.as_ref()))),
+        };
+        let view_source = send(HttpRequest::get(url))?;
+        let station_no = match STATION_NO_MATCHER.find(&view_source) {
+            Some(station_no) => station_no.as_str()[11..].parse()?,
+            None => return Err(Error::NotFound(format!("video {}", title_no))),
+        };
+        let bbs_no = match BBS_NO_MATCHER.find(&view_source) {
+            Some(bbs_no) => bbs_no.as_str()[7..].parse()?,
+            None => {
+                return Err(Error::Schema(
+                    "could not find nBbsNo in video page".to_owned(),
+                ))
+            }
+        };
+        let title = match TITLE_MATCHER.captures(&view_source) {
+            Some(captures) => captures[1].to_owned(),
+            None => String::new(),
+        };
         Ok(AfreecaVideo {
+            title,
             title_no,
             station_no,
             bbs_no,
         })
     }
 
-    pub fn new_unchecked<S: AsRef<str>>(title_no: S, station_no: S, bbs_no: S) -> AfreecaVideo {
+    /// Creates a new `AfreecaVideo` from the numbers that identify it on AfreecaTV
+    ///
+    /// The function will not check any values and may result in errors when calling other functions
+    pub fn new_unchecked(title: String, title_no: u32, station_no: u32, bbs_no: u32) -> Self {
         AfreecaVideo {
-            title_no: match title_no.as_ref().parse() {
-                Ok(title_no) => title_no,
-                Err(e) => exit_error(e),
-            },
-            station_no: match station_no.as_ref().parse() {
-                Ok(station_no) => station_no,
-                Err(e) => exit_error(e),
-            },
-            bbs_no: match bbs_no.as_ref().parse() {
-                Ok(bbs_no) => bbs_no,
-                Err(e) => exit_error(e),
-            },
+            title,
+            title_no,
+            station_no,
+            bbs_no,
         }
     }
     fn url(&self) -> String {
-        let a = format!("https://stbbs.afreecatv.com/api/video/get_video_info.php?nStationNo={}&nBbsNo={}&nTitleNo={}", self.station_no, self.bbs_no, self.title_no);
-        return a;
+        format!(
+            "{}/api/video/get_video_info.php?nStationNo={}&nBbsNo={}&nTitleNo={}",
+            endpoints().afreeca_stbbs,
+            self.station_no,
+            self.bbs_no,
+            self.title_no
+        )
     }
 
-    /// Identical function to `afreecatv_video::print_chat()` except that no Receiver<()> is required.
+    /// Returns the link to watch the video on AfreecaTV
+    pub fn url_to_watch(&self) -> String {
+        format!("https://vod.afreecatv.com/PLAYER/STATION/{}", self.title_no)
+    }
+
+    /// Returns an iterator over every chat message left on the video, in the order they were sent
     ///
-    /// Comments will be printed as soon as they are parsed and will not remain in a queue
+    /// The video is split into rows, each of which has its chat loaded lazily through `ChatLoadSplit.php` in 300 second windows
+    pub fn comments(&self) -> AfreecaComments {
+        self.comments_from(0)
+    }
+
+    /// Identical to `comments()`, except that reading starts at the window holding `offset` seconds into the video
     ///
-    /// This is recommended for single thread use case
-    pub(crate) fn print_chat_blocking(&self, filter: &Regex) {
-        let (tx, rx) = channel();
-        tx.send(()).unwrap(); //print immediately
-        self.print_chat(filter, rx)
-    }
-    pub(crate) fn print_chat(&self, filter: &Regex, rx: Receiver<()>) {
-        let xml = CLIENT
-            .get(self.url())
-            .header(COOKIE, DUMMY_COOKIE)
-            .send()
-            .unwrap()
-            .text()
-            .unwrap();
-        let mut row_key_iterator = ROW_KEY_MATCHER.find_iter(&xml);
+    /// Rows that end before `offset` are skipped by their duration, without requesting any of their windows
+    pub fn comments_from(&self, offset: u32) -> AfreecaComments {
+        AfreecaComments {
+            video: self.to_owned(),
+            rows: None,
+            start_secs: offset,
+            resume_at: None,
+            row_index: 0,
+            curr_secs: 0,
+            timestamp_secs_added: 0,
+            window: VecDeque::new(),
+            window_start: None,
+            returned: 0,
+        }
+    }
+
+    /// Identical to `comments()`, except that reading resumes at `cursor`, as returned by `AfreecaComments::cursor()`
+    pub fn comments_at(&self, cursor: AfreecaCursor) -> AfreecaComments {
+        AfreecaComments {
+            resume_at: Some(cursor),
+            ..self.comments()
+        }
+    }
+
+    /// Returns the `(row_key, row_time)` of every row that the video is split into
+    fn rows(&self) -> Result<Vec<(String, u32)>> {
+        let xml = send(HttpRequest::get(self.url()).header("Cookie", DUMMY_COOKIE))?;
+        let row_key_iterator = ROW_KEY_MATCHER.find_iter(&xml);
         let mut row_time_iterator = ROW_TIME_MATCHER.find_iter(&xml);
-        let mut timestamp_secs_added = 0;
-
-        let mut waiting_to_print = true;
-        let mut comment_queue: Vec<String> = Vec::new();
-        loop {
-            let row_key_regex = match row_key_iterator.next() {
-                Some(s) => s,
-                None => break,
+        let mut rows = Vec::new();
+        for row_key_regex in row_key_iterator {
+            let row_key = match row_key_regex.as_str().get(5..34) {
+                Some(row_key) => row_key.to_string(),
+                None => continue,
             };
-            let row_key = row_key_regex.as_str();
-            if row_key.len() < 8 {
-                continue;
-            }
-            let row_key = row_key[5..34].to_string();
-            let row_time = match row_time_iterator.next() {
+            match row_time_iterator.next() {
                 None => continue,
-                Some(time) => extract_digits(time.as_str()),
+                Some(time) => rows.push((row_key, extract_digits(time.as_str())?)),
             };
-            let mut curr_secs = 0;
-            loop {
-                let transcript_url = format!(
-                    "https://videoimg.afreecatv.com/php/ChatLoadSplit.php?rowKey={}_c&startTime={}",
-                    row_key, curr_secs
-                );
-                let xml = CLIENT.get(&transcript_url).send().unwrap().text().unwrap();
-                let doc = match Document::parse(&xml) {
-                    Ok(d) => d,
-                    Err(_) => break,
-                };
-                let nodes = doc.root().descendants();
-                for node in nodes {
-                    if node.tag_name().name() != "chat" {
-                        continue;
-                    }
-                    let comment: Vec<Node> = node.children().map(Node::from).collect();
-                    let name = comment[3].text().unwrap();
-                    let message = comment[4].text().unwrap();
-                    let time = comment[6].text().unwrap().parse::<f32>().unwrap() as u32;
-                    if filter.is_match(message) {
-                        let comment = format!(
-                            "[{}][{}]: {}",
-                            format_time(time + timestamp_secs_added),
-                            name,
-                            message
-                        );
-                        comment_queue.push(comment)
-                    }
-                    if waiting_to_print {
-                        if rx.try_recv().is_ok() {
-                            waiting_to_print = false
-                        }
-                    } else {
-                        print_queue(&mut comment_queue)
+        }
+        Ok(rows)
+    }
+}
+
+/// A lazy iterator over the chat of an `AfreecaVideo`, created by `AfreecaVideo::comments()`
+pub struct AfreecaComments {
+    video: AfreecaVideo,
+    /// The `(row_key, row_time)` of every row of the video, `None` until the first message is requested
+    rows: Option<Vec<(String, u32)>>,
+    /// Seconds into the video from which reading starts
+    start_secs: u32,
+    /// Where reading resumes instead of at `start_secs`, until the rows are loaded
+    resume_at: Option<AfreecaCursor>,
+    row_index: usize,
+    /// The `startTime` of the next window to request within the current row
+    curr_secs: u32,
+    timestamp_secs_added: u32,
+    window: VecDeque<ChatMessage>,
+    /// The row key and `startTime` of the last window requested, `None` until the first one is
+    window_start: Option<(String, u32)>,
+    /// How many messages of the last window requested have been returned
+    returned: usize,
+}
+
+/// Where `AfreecaComments` resume: a number of messages into a window of `ChatLoadSplit.php`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AfreecaCursor {
+    pub row_key: String,
+    pub start_time: u32,
+    /// How many messages of the window were already read
+    pub skip: usize,
+}
+
+impl AfreecaComments {
+    /// Skips to the window of `rows` that holds `self.start_secs`
+    fn seek(&mut self, rows: &[(String, u32)]) {
+        for (_, row_time) in rows {
+            if self.timestamp_secs_added + row_time > self.start_secs {
+                break;
+            }
+            self.timestamp_secs_added += row_time;
+            self.row_index += 1;
+        }
+        let secs_into_row = self.start_secs.saturating_sub(self.timestamp_secs_added);
+        self.curr_secs = secs_into_row - secs_into_row % 300;
+    }
+
+    /// Skips to the window of `rows` that `cursor` is in
+    fn seek_cursor(&mut self, rows: &[(String, u32)], cursor: &AfreecaCursor) -> Result<()> {
+        self.row_index = match rows
+            .iter()
+            .position(|(row_key, _)| *row_key == cursor.row_key)
+        {
+            Some(row_index) => row_index,
+            None => {
+                return Err(Error::NotFound(format!(
+                    "row {} of video {}",
+                    cursor.row_key, self.video.title_no
+                )))
+            }
+        };
+        self.timestamp_secs_added = rows[..self.row_index]
+            .iter()
+            .map(|(_, row_time)| row_time)
+            .sum();
+        self.curr_secs = cursor.start_time;
+        Ok(())
+    }
+
+    /// Loads the next window of chat into `self.window`, returning `false` once every row has been read
+    fn load_window(&mut self) -> Result<bool> {
+        if self.rows.is_none() {
+            let rows = self.video.rows()?;
+            match self.resume_at.to_owned() {
+                Some(cursor) => self.seek_cursor(&rows, &cursor)?,
+                None => self.seek(&rows),
+            }
+            self.rows = Some(rows);
+        }
+        let (row_key, row_time) = match self.rows.as_ref().and_then(|rows| rows.get(self.row_index))
+        {
+            None => return Ok(false),
+            Some((row_key, row_time)) => (row_key.to_owned(), *row_time),
+        };
+        let transcript_url = format!(
+            "{}/php/ChatLoadSplit.php?rowKey={}_c&startTime={}",
+            endpoints().afreeca_videoimg,
+            row_key,
+            self.curr_secs
+        );
+        let xml = send(HttpRequest::get(transcript_url))?;
+        self.window_start = Some((row_key, self.curr_secs));
+        self.returned = 0;
+        let video_id = self.video.title_no.to_string();
+        let is_last_window = match Document::parse(&xml) {
+            Ok(doc) => {
+                for node in doc.root().descendants() {
+                    if node.tag_name().name() == "chat" {
+                        let message = parse_chat(
+                            node,
+                            &video_id,
+                            &self.video.title,
+                            self.timestamp_secs_added,
+                        )?;
+                        self.window.push_back(message)
                     }
                 }
-                if curr_secs > row_time {
-                    timestamp_secs_added += row_time;
-                    break;
-                } else {
-                    curr_secs += 300;
-                }
+                self.curr_secs > row_time
             }
+            Err(_) => true,
+        };
+        //the messages of the window that were read before resuming are not returned again
+        if let Some(cursor) = self.resume_at.take() {
+            self.returned = cursor.skip.min(self.window.len());
+            self.window.drain(..self.returned);
         }
-        if !comment_queue.is_empty() {
-            rx.recv().unwrap();
-            print_queue(&mut comment_queue)
+        if is_last_window {
+            self.timestamp_secs_added += row_time;
+            self.row_index += 1;
+            self.curr_secs = 0;
+        } else {
+            self.curr_secs += 300;
         }
+        Ok(true)
+    }
+}
+
+/// Converts a `<chat>` node from `ChatLoadSplit.php` into a `ChatMessage` of the video `video_id`
+///
+/// `timestamp_secs_added` is the combined duration of all rows of the video before the one the node belongs to
+pub fn parse_chat(
+    node: Node,
+    video_id: &str,
+    video_title: &str,
+    timestamp_secs_added: u32,
+) -> Result<ChatMessage> {
+    let comment: Vec<Node> = node.children().collect();
+    let text = |index: usize| match comment.get(index).and_then(|field| field.text()) {
+        Some(text) => Ok(text),
+        None => Err(Error::Schema(format!("<chat> is missing field {}", index))),
+    };
+    let name = text(3)?;
+    let body = text(4)?;
+    let time = text(6)?.parse::<f64>()?;
+    let mut raw = Map::new();
+    for field in node.children().filter(Node::is_element) {
+        raw.insert(
+            field.tag_name().name().to_owned(),
+            Value::String(field.text().unwrap_or_default().to_owned()),
+        );
+    }
+    let user_id = raw
+        .get("u")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_owned();
+    Ok(ChatMessage {
+        platform: Platform::AfreecaTV,
+        video_id: video_id.to_owned(),
+        video_title: video_title.to_owned(),
+        offset: time + f64::from(timestamp_secs_added),
+        login: user_id.to_owned(),
+        user_id,
+        display_name: name.to_owned(),
+        color: None,
+        badges: Vec::new(),
+        emotes: Vec::new(),
+        bits: 0,
+        notice: None,
+        body: body.to_owned(),
+        raw: Value::Object(raw),
+    })
+}
+
+/// Converts every `<chat>` node of a `ChatLoadSplit.php` document into a `ChatMessage` of the video `video_id`
+pub fn parse_chat_xml(
+    xml: &str,
+    video_id: &str,
+    video_title: &str,
+    timestamp_secs_added: u32,
+) -> Result<Vec<ChatMessage>> {
+    let doc = match Document::parse(xml) {
+        Ok(doc) => doc,
+        Err(e) => return Err(Error::Parse(format!("chat XML: {}", e))),
+    };
+    let mut messages = Vec::new();
+    for node in doc.root().descendants() {
+        if node.tag_name().name() == "chat" {
+            messages.push(parse_chat(
+                node,
+                video_id,
+                video_title,
+                timestamp_secs_added,
+            )?)
+        }
+    }
+    Ok(messages)
+}
+
+/// Returns a link to the video `title_no` on AfreecaTV that starts playing at `offset` seconds
+pub fn video_link(title_no: &str, offset: f64) -> String {
+    format!(
+        "https://vod.afreecatv.com/PLAYER/STATION/{}?change_second={}",
+        title_no, offset as u32
+    )
+}
+
+impl Iterator for AfreecaComments {
+    type Item = Result<ChatMessage>;
+
+    /// Returns the next chat message, requesting the next window when the current one runs out
+    ///
+    /// Once an `Err` has been returned, the iterator is finished
+    fn next(&mut self) -> Option<Result<ChatMessage>> {
+        while self.window.is_empty() {
+            match self.load_window() {
+                Ok(true) => continue,
+                Ok(false) => return None,
+                Err(e) => {
+                    self.window.clear();
+                    self.rows = Some(Vec::new());
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.returned += 1;
+        self.window.pop_front().map(Ok)
+    }
+}
+
+impl Resumable for AfreecaComments {
+    type Cursor = AfreecaCursor;
+
+    fn cursor(&self) -> Option<AfreecaCursor> {
+        let (row_key, start_time) = self.window_start.to_owned()?;
+        Some(AfreecaCursor {
+            row_key,
+            start_time,
+            skip: self.returned,
+        })
+    }
+}
+
+impl VideoSource for AfreecaVideo {
+    type Comments = AfreecaComments;
+
+    fn platform(&self) -> Platform {
+        Platform::AfreecaTV
+    }
+
+    fn id(&self) -> String {
+        self.title_no.to_string()
+    }
+
+    fn title(&self) -> String {
+        self.title.to_owned()
+    }
+
+    fn comments_from(&self, offset: f64) -> AfreecaComments {
+        AfreecaVideo::comments_from(self, offset as u32)
+    }
+
+    fn comments_at(&self, cursor: AfreecaCursor) -> AfreecaComments {
+        AfreecaVideo::comments_at(self, cursor)
+    }
+
+    fn playback_url(&self) -> Result<String> {
+        Ok(self.url_to_watch())
+    }
+
+    fn link_at(&self, offset: f64) -> String {
+        video_link(&self.id(), offset)
     }
 }
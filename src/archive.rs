@@ -0,0 +1,650 @@
+//! An on-disk archive of the whole chat of every video read, so that later searches do not download it again
+//!
+//! The chat of a video is stored as one JSON `ChatMessage` per line in `{dir}/{platform}/{id}.ndjson`, and is only
+//! stored once every message of the video has been read, since the chat of a finished video never changes.
+//! Until then it is written to `{id}.ndjson.partial`, along with a cursor in `{id}.ndjson.cursor` that says where
+//! the next read resumes when reading stops part way.
+//! An archive made with `Archive::checkpoints()` only keeps the partial files, so that reading can be resumed without
+//! storing the chat of every video.
+//! Like the `Transport`, the archive is shared by every thread, so it is set once with `set_archive()`
+use crate::{
+    chat::{ChatMessage, Platform},
+    error::{Error, Result},
+    source::{Resumable, VideoSource},
+};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::VecDeque,
+    env,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Lines, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock, Weak},
+};
+
+/// A directory holding the chat of every video that has been read in full
+#[derive(Clone, Debug)]
+pub struct Archive {
+    dir: PathBuf,
+    /// `false` when only chat that stopped being read part way is kept, to be resumed
+    stores: bool,
+}
+
+impl Archive {
+    /// Creates an archive in `dir`, which is created when the first video is stored
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Archive {
+            dir: dir.into(),
+            stores: true,
+        }
+    }
+
+    /// Creates an archive in `dir` that only keeps chat that stopped being read part way, until it is resumed
+    ///
+    /// Chat read in full is thrown away instead of being stored, and stored chat is never read
+    pub fn checkpoints<P: Into<PathBuf>>(dir: P) -> Self {
+        Archive {
+            dir: dir.into(),
+            stores: false,
+        }
+    }
+
+    /// Returns `false` if the archive was made with `Archive::checkpoints()`
+    pub fn stores_chat(&self) -> bool {
+        self.stores
+    }
+
+    /// Returns the directory of the archive
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the file that the chat of the video `id` on `platform` is stored in
+    pub fn path(&self, platform: Platform, id: &str) -> PathBuf {
+        self.dir
+            .join(platform.to_string())
+            .join(format!("{}.ndjson", id))
+    }
+
+    /// Returns the directory that the `index::ChatIndex` of `channel` on `platform` is stored in
+    pub fn index_path(&self, platform: Platform, channel: &str) -> PathBuf {
+        self.dir
+            .join(platform.to_string())
+            .join(format!("{}.index", channel.to_lowercase()))
+    }
+
+    /// Returns `true` if the whole chat of the video `id` on `platform` is stored
+    pub fn contains(&self, platform: Platform, id: &str) -> bool {
+        self.path(platform, id).is_file()
+    }
+
+    /// Returns an iterator over the stored chat of the video `id` on `platform`
+    pub fn read(&self, platform: Platform, id: &str) -> Result<StoredComments> {
+        let path = self.path(platform, id);
+        match File::open(&path) {
+            Ok(file) => Ok(StoredComments {
+                lines: BufReader::new(file).lines(),
+                path,
+                is_finished: false,
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(Error::NotFound(format!(
+                "the archived chat of {} v{} in {}",
+                platform,
+                id,
+                self.dir.display()
+            ))),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Returns the IDs of every video of `platform` whose chat is stored, in no particular order
+    pub fn videos(&self, platform: Platform) -> Result<Vec<String>> {
+        let dir = self.dir.join(platform.to_string());
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "ndjson")
+            {
+                if let Some(id) = path.file_stem() {
+                    ids.push(id.to_string_lossy().into_owned())
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Returns the comments of `video` from `offset` seconds, from the archive when they are stored
+    ///
+    /// Otherwise they are read from the platform, and stored once every message has been read.
+    /// Chat that stopped being read part way is kept with where it stopped, and the next read resumes there.
+    /// Chat read from part way into the video is never stored, as it would be incomplete
+    pub fn comments<V: VideoSource>(&self, video: &V, offset: f64) -> Comments<V::Comments> {
+        let (platform, id) = (video.platform(), video.id());
+        if self.stores && self.contains(platform, &id) {
+            return match self.read(platform, &id) {
+                Ok(stored) => Comments::Stored(stored),
+                Err(e) => Comments::Failed(Some(e)),
+            };
+        }
+        if offset > 0.0 {
+            return Comments::Live(video.comments_from(offset));
+        }
+        let path = self.path(platform, &id);
+        let partial = path.with_extension("ndjson.partial");
+        if let Some((resumed, checkpoint, writer)) = resume(&partial) {
+            if let Ok(cursor) = serde_json::from_value(checkpoint.cursor.to_owned()) {
+                return Comments::Storing {
+                    progress: Some(Progress::start(
+                        writer,
+                        partial,
+                        checkpoint.messages,
+                        Some(checkpoint.cursor),
+                        self.stores,
+                    )),
+                    resumed,
+                    comments: video.comments_at(cursor),
+                    path,
+                };
+            }
+        }
+        let writer = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| File::create(&partial));
+        match writer {
+            Ok(file) => Comments::Storing {
+                resumed: VecDeque::new(),
+                comments: video.comments(),
+                progress: Some(Progress::start(file, partial, 0, None, self.stores)),
+                path,
+            },
+            //the archive is only a cache, so chat is still read when it cannot be written to
+            Err(_) => Comments::Live(video.comments()),
+        }
+    }
+}
+
+/// Returns the messages kept in `partial` by an earlier read that stopped part way, its `Checkpoint`, and `partial`
+/// opened to append to
+///
+/// Lines written after the checkpoint are cut off, since they may not have been written in full
+fn resume(partial: &Path) -> Option<(VecDeque<ChatMessage>, Checkpoint, File)> {
+    let checkpoint: Checkpoint =
+        serde_json::from_reader(File::open(checkpoint_path(partial)).ok()?).ok()?;
+    let mut resumed = VecDeque::new();
+    let mut length = 0;
+    for line in BufReader::new(File::open(partial).ok()?)
+        .lines()
+        .take(checkpoint.messages)
+    {
+        let line = line.ok()?;
+        resumed.push_back(serde_json::from_str(&line).ok()?);
+        length += line.len() as u64 + 1
+    }
+    if resumed.len() < checkpoint.messages {
+        return None;
+    }
+    let mut file = OpenOptions::new().write(true).open(partial).ok()?;
+    file.set_len(length).ok()?;
+    file.seek(SeekFrom::End(0)).ok()?;
+    Some((resumed, checkpoint, file))
+}
+
+/// Returns the file that holds the `Checkpoint` of the chat being stored in `partial`
+fn checkpoint_path(partial: &Path) -> PathBuf {
+    partial.with_extension("cursor")
+}
+
+/// How much of the chat of a video was stored before reading it stopped, and where to resume it
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    /// How many messages at the start of the partial file are complete
+    messages: usize,
+    /// The `Resumable::Cursor` right after the last of those messages
+    cursor: Value,
+}
+
+/// The chat of a video being written to a partial file, which is shared with `checkpoint_all()`
+pub struct Progress {
+    writer: BufWriter<File>,
+    /// How many messages have been written
+    messages: usize,
+    /// The cursor right after the last message written, `None` if reading cannot be resumed there
+    cursor: Option<Value>,
+    partial: PathBuf,
+    /// `false` when the chat is thrown away once it has been read in full
+    stores: bool,
+}
+
+lazy_static! {
+    /// Every video whose chat is being stored, to be checkpointed when the run is interrupted
+    static ref PROGRESS: Mutex<Vec<Weak<Mutex<Progress>>>> = Mutex::new(Vec::new());
+}
+
+impl Progress {
+    /// Starts storing chat in `partial`, after the `messages` already written to `file` that end at `cursor`
+    fn start(
+        file: File,
+        partial: PathBuf,
+        messages: usize,
+        cursor: Option<Value>,
+        stores: bool,
+    ) -> Arc<Mutex<Progress>> {
+        let progress = Arc::new(Mutex::new(Progress {
+            writer: BufWriter::new(file),
+            messages,
+            cursor,
+            partial,
+            stores,
+        }));
+        let mut storing = PROGRESS.lock().unwrap();
+        storing.retain(|progress| progress.strong_count() > 0);
+        storing.push(Arc::downgrade(&progress));
+        progress
+    }
+
+    fn write(&mut self, message: &ChatMessage, cursor: Option<Value>) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, message)?;
+        self.writer.write_all(b"\n")?;
+        self.messages += 1;
+        self.cursor = cursor;
+        Ok(())
+    }
+
+    /// Keeps every message written so far along with where to resume, or removes them when reading cannot be resumed
+    fn checkpoint(&mut self) {
+        let checkpoint = match self.cursor.to_owned() {
+            Some(cursor) if self.messages > 0 => Checkpoint {
+                messages: self.messages,
+                cursor,
+            },
+            _ => return self.discard(),
+        };
+        let kept = self.writer.flush().is_ok()
+            && File::create(checkpoint_path(&self.partial))
+                .map_err(Error::from)
+                .and_then(|file| serde_json::to_writer(file, &checkpoint).map_err(Error::from))
+                .is_ok();
+        if !kept {
+            self.discard()
+        }
+    }
+
+    /// Moves the chat to `path` once every message has been written, or removes it when it is not stored
+    fn finish(&mut self, path: &Path) {
+        if self.stores && self.writer.flush().is_ok() && fs::rename(&self.partial, path).is_ok() {
+            let _ = fs::remove_file(checkpoint_path(&self.partial));
+        } else {
+            self.discard()
+        }
+    }
+
+    fn discard(&mut self) {
+        let _ = fs::remove_file(&self.partial);
+        let _ = fs::remove_file(checkpoint_path(&self.partial));
+    }
+}
+
+/// Keeps what has been read of the chat of every video being stored, so that the next read resumes there instead of
+/// reading it again
+///
+/// Meant to be called when the run is interrupted, such as by Ctrl-C, as chat that stops being read otherwise is
+/// already kept
+pub fn checkpoint_all() {
+    for progress in PROGRESS.lock().unwrap().iter().filter_map(Weak::upgrade) {
+        progress.lock().unwrap().checkpoint()
+    }
+}
+
+/// The iterator returned by `Archive::read()`
+///
+/// The iterator is finished after the first `Err` it returns
+pub struct StoredComments {
+    lines: Lines<BufReader<File>>,
+    path: PathBuf,
+    is_finished: bool,
+}
+
+impl Iterator for StoredComments {
+    type Item = Result<ChatMessage>;
+
+    fn next(&mut self) -> Option<Result<ChatMessage>> {
+        if self.is_finished {
+            return None;
+        }
+        let result = match self.lines.next()? {
+            Ok(line) => serde_json::from_str(&line).map_err(|e| {
+                Error::Parse(format!(
+                    "the archived chat in {}: {}",
+                    self.path.display(),
+                    e
+                ))
+            }),
+            Err(e) => Err(e.into()),
+        };
+        self.is_finished = result.is_err();
+        Some(result)
+    }
+}
+
+/// The iterator returned by `Archive::comments()`
+pub enum Comments<C> {
+    /// Chat read from the archive
+    Stored(StoredComments),
+    /// Chat read from the platform and written to a partial file, which is moved to `path` once it is complete
+    Storing {
+        /// The messages kept by an earlier read that stopped part way, which are returned first
+        resumed: VecDeque<ChatMessage>,
+        comments: C,
+        /// `None` once writing has stopped, after which the chat is only read
+        progress: Option<Arc<Mutex<Progress>>>,
+        path: PathBuf,
+    },
+    /// Chat read from the platform without being stored
+    Live(C),
+    /// The archive could not be read, which is returned once
+    Failed(Option<Error>),
+}
+
+impl<C: Resumable> Iterator for Comments<C> {
+    type Item = Result<ChatMessage>;
+
+    fn next(&mut self) -> Option<Result<ChatMessage>> {
+        match self {
+            Comments::Stored(stored) => stored.next(),
+            Comments::Live(comments) => comments.next(),
+            Comments::Failed(e) => e.take().map(Err),
+            Comments::Storing {
+                resumed,
+                comments,
+                progress,
+                path,
+            } => {
+                if let Some(message) = resumed.pop_front() {
+                    return Some(Ok(message));
+                }
+                let next = comments.next();
+                let stopped = match (&next, progress.as_ref()) {
+                    (_, None) => return next,
+                    (Some(Ok(message)), Some(progress)) => {
+                        let cursor = comments
+                            .cursor()
+                            .and_then(|cursor| serde_json::to_value(cursor).ok());
+                        let mut progress = progress.lock().unwrap();
+                        match progress.write(message, cursor) {
+                            Ok(_) => false,
+                            Err(_) => {
+                                progress.discard();
+                                true
+                            }
+                        }
+                    }
+                    //what was read before the error is kept, and the next read resumes after it
+                    (Some(Err(_)), Some(progress)) => {
+                        progress.lock().unwrap().checkpoint();
+                        true
+                    }
+                    (None, Some(progress)) => {
+                        progress.lock().unwrap().finish(path);
+                        true
+                    }
+                };
+                if stopped {
+                    *progress = None
+                }
+                next
+            }
+        }
+    }
+}
+
+impl<C> Drop for Comments<C> {
+    /// Chat that stopped being read before the end, such as after `--max-count` matches, is kept to be resumed
+    fn drop(&mut self) {
+        if let Comments::Storing {
+            progress: Some(progress),
+            ..
+        } = self
+        {
+            progress.lock().unwrap().checkpoint()
+        }
+    }
+}
+
+/// Returns the directory the archive is kept in when none is given: `chat_reader` in the user's cache directory
+pub fn default_dir() -> PathBuf {
+    let cache = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .unwrap_or_else(env::temp_dir);
+    cache.join("chat_reader")
+}
+
+lazy_static! {
+    static ref ARCHIVE: RwLock<Option<Arc<Archive>>> = RwLock::new(None);
+}
+
+/// Sets the archive that the chat of every following video is read from and stored in, or `None` to always read from the platform
+pub fn set_archive(archive: Option<Archive>) {
+    *ARCHIVE.write().unwrap() = archive.map(Arc::new)
+}
+
+/// Returns the archive that chat is currently read from and stored in, if there is one
+pub fn archive() -> Option<Arc<Archive>> {
+    ARCHIVE.read().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::tests::message;
+
+    /// A video of `count` messages, numbered from `0`, whose chat fails when it gets to message `fails_at`
+    #[derive(Clone)]
+    struct Numbers {
+        count: usize,
+        fails_at: Option<usize>,
+    }
+
+    struct NumberComments {
+        next: usize,
+        video: Numbers,
+    }
+
+    impl Iterator for NumberComments {
+        type Item = Result<ChatMessage>;
+
+        fn next(&mut self) -> Option<Result<ChatMessage>> {
+            if self.video.fails_at == Some(self.next) {
+                self.next = self.video.count;
+                return Some(Err(Error::Status {
+                    url: "https://example.com".to_owned(),
+                    status: 503,
+                }));
+            }
+            if self.next >= self.video.count {
+                return None;
+            }
+            self.next += 1;
+            let number = self.next - 1;
+            Some(Ok(message(number as f64, "alice", &number.to_string())))
+        }
+    }
+
+    impl Resumable for NumberComments {
+        type Cursor = usize;
+
+        fn cursor(&self) -> Option<usize> {
+            Some(self.next)
+        }
+    }
+
+    impl VideoSource for Numbers {
+        type Comments = NumberComments;
+
+        fn platform(&self) -> Platform {
+            Platform::Twitch
+        }
+
+        fn id(&self) -> String {
+            "1".to_owned()
+        }
+
+        fn title(&self) -> String {
+            "Test VOD".to_owned()
+        }
+
+        fn comments_from(&self, offset: f64) -> NumberComments {
+            self.comments_at(offset as usize)
+        }
+
+        fn comments_at(&self, cursor: usize) -> NumberComments {
+            NumberComments {
+                next: cursor,
+                video: self.to_owned(),
+            }
+        }
+
+        fn playback_url(&self) -> Result<String> {
+            Ok(String::new())
+        }
+
+        fn link_at(&self, _: f64) -> String {
+            String::new()
+        }
+    }
+
+    fn archive(name: &str) -> Archive {
+        let dir = env::temp_dir().join(format!(
+            "chat_reader_archive_{}_{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        Archive::new(dir)
+    }
+
+    /// Returns the bodies of the messages read, and whether reading failed
+    fn read<V: VideoSource>(archive: &Archive, video: &V) -> (Vec<String>, bool) {
+        let mut bodies = Vec::new();
+        for message in archive.comments(video, 0.0) {
+            match message {
+                Ok(message) => bodies.push(message.body),
+                Err(_) => return (bodies, true),
+            }
+        }
+        (bodies, false)
+    }
+
+    fn numbers(range: std::ops::Range<usize>) -> Vec<String> {
+        range.map(|number| number.to_string()).collect()
+    }
+
+    #[test]
+    fn chat_is_stored_once_read_in_full() {
+        let archive = archive("full");
+        let video = Numbers {
+            count: 10,
+            fails_at: None,
+        };
+        assert_eq!(read(&archive, &video), (numbers(0..10), false));
+        assert!(archive.contains(Platform::Twitch, "1"));
+        //the stored chat is read instead of the platform, which would fail right away
+        let failing = Numbers {
+            count: 10,
+            fails_at: Some(0),
+        };
+        assert_eq!(read(&archive, &failing), (numbers(0..10), false));
+        fs::remove_dir_all(archive.dir()).unwrap()
+    }
+
+    #[test]
+    fn failed_chat_resumes_after_what_was_read() {
+        let archive = archive("failed");
+        let failing = Numbers {
+            count: 10,
+            fails_at: Some(4),
+        };
+        assert_eq!(read(&archive, &failing), (numbers(0..4), true));
+        assert!(!archive.contains(Platform::Twitch, "1"));
+        //a video that fails at the start can only be read in full if reading resumes at message 4
+        let resumed = Numbers {
+            count: 10,
+            fails_at: Some(0),
+        };
+        assert_eq!(read(&archive, &resumed), (numbers(0..10), false));
+        assert!(archive.contains(Platform::Twitch, "1"));
+        fs::remove_dir_all(archive.dir()).unwrap()
+    }
+
+    #[test]
+    fn chat_that_stops_being_read_is_kept() {
+        let archive = archive("stopped");
+        let video = Numbers {
+            count: 10,
+            fails_at: None,
+        };
+        assert_eq!(archive.comments(&video, 0.0).take(3).count(), 3);
+        let partial = archive
+            .path(Platform::Twitch, "1")
+            .with_extension("ndjson.partial");
+        //a line cut off part way by an interrupted write is dropped
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&partial)
+            .unwrap()
+            .write_all(b"{\"platform\":")
+            .unwrap();
+        let resumed = Numbers {
+            count: 10,
+            fails_at: Some(0),
+        };
+        assert_eq!(read(&archive, &resumed), (numbers(0..10), false));
+        assert!(!partial.exists() && !checkpoint_path(&partial).exists());
+        fs::remove_dir_all(archive.dir()).unwrap()
+    }
+
+    #[test]
+    fn checkpoints_resume_chat_without_storing_it() {
+        let archive = Archive::checkpoints(archive("checkpoints").dir());
+        let failing = Numbers {
+            count: 10,
+            fails_at: Some(4),
+        };
+        assert_eq!(read(&archive, &failing), (numbers(0..4), true));
+        let resumed = Numbers {
+            count: 10,
+            fails_at: Some(0),
+        };
+        assert_eq!(read(&archive, &resumed), (numbers(0..10), false));
+        assert!(!archive.contains(Platform::Twitch, "1"));
+        assert_eq!(
+            archive.videos(Platform::Twitch).unwrap(),
+            Vec::<String>::new()
+        );
+        let partial = archive
+            .path(Platform::Twitch, "1")
+            .with_extension("ndjson.partial");
+        assert!(!partial.exists() && !checkpoint_path(&partial).exists());
+        fs::remove_dir_all(archive.dir()).unwrap()
+    }
+
+    #[test]
+    fn chat_read_from_an_offset_is_not_stored() {
+        let archive = archive("offset");
+        let video = Numbers {
+            count: 10,
+            fails_at: None,
+        };
+        assert_eq!(archive.comments(&video, 5.0).count(), 5);
+        assert!(!archive.dir().exists());
+    }
+}
@@ -0,0 +1,394 @@
+use crate::{
+    error::Result,
+    output,
+    query::{Query, Selected},
+    tools::{format_time, hex_to_rgb},
+};
+use crossterm::{
+    execute,
+    style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{fmt, io::stdout, sync::mpsc::Receiver};
+
+/// The streaming platform that a `ChatMessage` was read from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    Twitch,
+    AfreecaTV,
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Platform::Twitch => write!(f, "twitch"),
+            Platform::AfreecaTV => write!(f, "afreecatv"),
+        }
+    }
+}
+
+/// Colors given to users that have not picked a chat color, in the same way Twitch does
+const DEFAULT_COLORS: [(u8, u8, u8); 15] = [
+    (0xFF, 0x00, 0x00),
+    (0x00, 0x00, 0xFF),
+    (0x00, 0x80, 0x00),
+    (0xB2, 0x22, 0x22),
+    (0xFF, 0x7F, 0x50),
+    (0x9A, 0xCD, 0x32),
+    (0xFF, 0x45, 0x00),
+    (0x2E, 0x8B, 0x57),
+    (0xDA, 0xA5, 0x20),
+    (0xD2, 0x69, 0x1E),
+    (0x5F, 0x9E, 0xA0),
+    (0x1E, 0x90, 0xFF),
+    (0xFF, 0x69, 0xB4),
+    (0x8A, 0x2B, 0xE2),
+    (0x00, 0xFF, 0x7F),
+];
+
+/// A badge shown next to a user's name in chat
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Badge {
+    /// The kind of badge, such as `moderator`, `subscriber` or `vip`
+    pub name: String,
+    /// Which badge of the kind, such as the number of months for `subscriber`
+    pub version: String,
+}
+
+impl fmt::Display for Badge {
+    /// Writes the badge as `name/version`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.name, self.version)
+    }
+}
+
+/// An emote used in the body of a `ChatMessage`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Emote {
+    /// The platform specific ID of the emote, from which its image can be found, or empty when it is not known
+    pub id: String,
+    /// The text that the emote replaces in the body, such as `Kappa`
+    pub name: String,
+    /// The byte offset of the emote's `name` in the body
+    pub start: usize,
+}
+
+impl Emote {
+    /// Returns a link to the image of the emote on Twitch's CDN
+    pub fn image_url(&self) -> String {
+        format!(
+            "https://static-cdn.jtvnw.net/emoticons/v2/{}/default/dark/1.0",
+            self.id
+        )
+    }
+}
+
+/// A single chat message left on a video, independent of the platform it came from
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub platform: Platform,
+    /// The ID of the video the message belongs to, such as a `TwitchVOD.id` or an `AfreecaVideo.title_no`
+    pub video_id: String,
+    pub video_title: String,
+    /// Seconds since the start of the video at which the message was sent
+    pub offset: f64,
+    pub user_id: String,
+    pub login: String,
+    pub display_name: String,
+    /// The user's chat color as a `#RRGGBB` hex string, if the platform provides one
+    pub color: Option<String>,
+    /// The badges shown next to the user's name, such as `moderator` or `subscriber`, if the platform provides them
+    pub badges: Vec<Badge>,
+    /// The emotes used in the body, in the order they appear, if the platform provides them
+    pub emotes: Vec<Emote>,
+    /// The bits cheered with the message, or `0`
+    pub bits: u32,
+    /// The kind of user notice the message is, such as `sub`, `resub` or `subgift`, or `None` for a regular message
+    pub notice: Option<String>,
+    pub body: String,
+    /// The untouched fields of the message as the platform returned them
+    pub raw: Value,
+}
+
+impl ChatMessage {
+    /// Returns the `offset` of the message formatted as `HH:MM:SS`
+    pub fn timestamp(&self) -> String {
+        format_time(self.offset as u32)
+    }
+
+    /// Returns the `color` of the message as a terminal `Color`
+    ///
+    /// Messages without a color, or with a color that cannot be parsed, use the terminal's default color
+    pub fn terminal_color(&self) -> Color {
+        match self
+            .color
+            .as_ref()
+            .filter(|color| {
+                color.len() == 7
+                    && color.starts_with('#')
+                    && color[1..].bytes().all(|byte| byte.is_ascii_hexdigit())
+            })
+            .map(hex_to_rgb)
+        {
+            Some(Ok(color)) => color,
+            _ => Color::Reset,
+        }
+    }
+
+    /// Returns the color of the message's user as `(r, g, b)`
+    ///
+    /// Users without a color get one picked from their name, so that they are still told apart
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        match self.terminal_color() {
+            Color::Rgb { r, g, b } => (r, g, b),
+            _ => {
+                let name = if self.login.is_empty() {
+                    &self.display_name
+                } else {
+                    &self.login
+                };
+                let sum = name.bytes().map(usize::from).sum::<usize>();
+                DEFAULT_COLORS[sum % DEFAULT_COLORS.len()]
+            }
+        }
+    }
+
+    /// Returns the body split into plain text and emotes, in order
+    ///
+    /// Emotes whose position does not line up with the body are treated as plain text
+    pub fn fragments(&self) -> Vec<(&str, Option<&Emote>)> {
+        let mut fragments = Vec::new();
+        let mut position = 0;
+        for emote in &self.emotes {
+            let end = emote.start + emote.name.len();
+            if emote.start < position || self.body.get(emote.start..end) != Some(&emote.name) {
+                continue;
+            }
+            if emote.start > position {
+                fragments.push((&self.body[position..emote.start], None))
+            }
+            fragments.push((&self.body[emote.start..end], Some(emote)));
+            position = end
+        }
+        if position < self.body.len() {
+            fragments.push((&self.body[position..], None))
+        }
+        fragments
+    }
+
+    /// Returns `true` if the body is made of nothing but emotes
+    pub fn is_emote_only(&self) -> bool {
+        let fragments = self.fragments();
+        fragments.iter().any(|(_, emote)| emote.is_some())
+            && fragments
+                .iter()
+                .all(|(text, emote)| emote.is_some() || text.trim().is_empty())
+    }
+
+    /// Prints the message to console in the form `[HH:MM:SS][display_name]: body`
+    ///
+    /// User notices and bits are tagged after the name, as in `[HH:MM:SS][display_name][resub][100 bits]: body`,
+    /// and badges are listed before it when `output::show_badges()`
+    ///
+    /// The name is only colored, and emotes only shown in bold, when stdout is a terminal
+    pub fn print(&self) {
+        self.print_with_separator(':')
+    }
+
+    /// Prints the message to console as the context of a match, in the form `[HH:MM:SS][display_name]- body`
+    pub fn print_context(&self) {
+        self.print_with_separator('-')
+    }
+
+    /// Returns the `[notice]` and `[N bits]` tags printed after the name, and the badges printed before it when `output::show_badges()`
+    fn tags(&self) -> (String, String) {
+        let badges = if output::show_badges() && !self.badges.is_empty() {
+            let badges: Vec<String> = self.badges.iter().map(Badge::to_string).collect();
+            format!("[{}]", badges.join(" "))
+        } else {
+            String::new()
+        };
+        let mut after = String::new();
+        if let Some(notice) = &self.notice {
+            after.push_str(&format!("[{}]", notice))
+        }
+        if self.bits > 0 {
+            after.push_str(&format!("[{} bits]", self.bits))
+        }
+        (badges, after)
+    }
+
+    fn print_with_separator(&self, separator: char) {
+        let (badges, after) = self.tags();
+        if !output::use_colors() {
+            return println!(
+                "[{}]{}[{}]{}{} {}",
+                self.timestamp(),
+                badges,
+                self.display_name,
+                after,
+                separator,
+                self.body
+            );
+        }
+        let mut stdout = stdout();
+        execute!(
+            stdout,
+            Print(format!("[{}]{}[", self.timestamp(), badges)),
+            SetForegroundColor(self.terminal_color()),
+            Print(&self.display_name),
+            ResetColor,
+            Print("]"),
+            SetForegroundColor(Color::Yellow),
+            Print(after),
+            ResetColor,
+            Print(format!("{} ", separator))
+        )
+        .unwrap();
+        for (text, emote) in self.fragments() {
+            match emote {
+                Some(_) => execute!(
+                    stdout,
+                    SetAttribute(Attribute::Bold),
+                    Print(text),
+                    SetAttribute(Attribute::Reset)
+                ),
+                None => execute!(stdout, Print(text)),
+            }
+            .unwrap()
+        }
+        execute!(stdout, Print("\n")).unwrap();
+    }
+}
+
+/// Function to print all `Selected` messages in a Vec whilst emptying it, in the current `output::Format`
+pub fn print_messages(messages: &mut Vec<Selected>) -> Result<()> {
+    for message in messages.drain(..) {
+        output::write_message(&message)?
+    }
+    Ok(())
+}
+
+/// Prints every message of `messages` that is selected by `query`
+///
+/// The `rx: Receiver<()>` is used to determine when the messages should be printed out
+///
+/// By default, the outputs are queued into `comment_queue` and then will be allowed to print only when `rx` receives a `()` from a `Sender<()>`
+///
+/// Reading stops at the first `Err` in `messages`, which is returned once every message before it has been printed
+pub fn print_chat<I: IntoIterator<Item = Result<ChatMessage>>>(
+    messages: I,
+    query: &Query,
+    rx: Receiver<()>,
+) -> Result<()> {
+    print_chat_after(messages, query, rx, &mut 0)
+}
+
+/// Identical function to `print_chat()` except that the first `printed` messages selected by `query` are not printed again
+///
+/// `printed` is left at the number of messages selected before reading stopped,
+/// so that chat which failed part way can be read again without repeating any output or going over the query's `max_count`
+pub fn print_chat_after<I: IntoIterator<Item = Result<ChatMessage>>>(
+    messages: I,
+    query: &Query,
+    rx: Receiver<()>,
+    printed: &mut usize,
+) -> Result<()> {
+    let mut comment_queue: Vec<Selected> = Vec::new();
+    let mut waiting_to_print = true;
+    let mut result = Ok(());
+    for (index, message) in query.select(messages).enumerate() {
+        let message = match message {
+            Ok(message) => message,
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        };
+        //the same chat is selected the same way every time it is read
+        if index < *printed {
+            continue;
+        }
+        *printed = index + 1;
+        comment_queue.push(message);
+        if waiting_to_print {
+            if rx.try_recv().is_ok() {
+                waiting_to_print = false
+            }
+        } else {
+            print_messages(&mut comment_queue)?
+        }
+    }
+    if !comment_queue.is_empty() {
+        if waiting_to_print {
+            //the receiving end may have given up on this chat, in which case there is nobody to print for
+            if rx.recv().is_err() {
+                return result;
+            }
+        }
+        print_messages(&mut comment_queue)?
+    }
+    result
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Returns a Twitch message of the video `1` with nothing but an offset, a user and a body
+    pub(crate) fn message(offset: f64, name: &str, body: &str) -> ChatMessage {
+        ChatMessage {
+            platform: Platform::Twitch,
+            video_id: "1".to_owned(),
+            video_title: "Test VOD".to_owned(),
+            offset,
+            user_id: String::new(),
+            login: name.to_lowercase(),
+            display_name: name.to_owned(),
+            color: None,
+            badges: Vec::new(),
+            emotes: Vec::new(),
+            bits: 0,
+            notice: None,
+            body: body.to_owned(),
+            raw: Value::Null,
+        }
+    }
+
+    #[test]
+    fn only_hex_colors_are_parsed() {
+        let mut colored = message(0.0, "alice", "hi");
+        colored.color = Some("#FF8000".to_owned());
+        assert_eq!(colored.rgb(), (255, 128, 0));
+        for color in ["#aé123", "#GGGGGG", "#+1+2+3", "FF8000F", "#FF80"] {
+            colored.color = Some(color.to_owned());
+            assert_eq!(colored.terminal_color(), Color::Reset, "{}", color);
+            assert_eq!(colored.rgb(), message(0.0, "alice", "hi").rgb());
+        }
+    }
+
+    #[test]
+    fn emotes_split_the_body() {
+        let mut kappa = message(0.0, "alice", "hi Kappa there");
+        kappa.emotes = vec![Emote {
+            id: "25".to_owned(),
+            name: "Kappa".to_owned(),
+            start: 3,
+        }];
+        let fragments: Vec<(&str, bool)> = kappa
+            .fragments()
+            .into_iter()
+            .map(|(text, emote)| (text, emote.is_some()))
+            .collect();
+        assert_eq!(
+            fragments,
+            vec![("hi ", false), ("Kappa", true), (" there", false)]
+        );
+        assert!(!kappa.is_emote_only());
+        //an emote that does not line up with the body is plain text
+        kappa.emotes[0].start = 4;
+        assert_eq!(kappa.fragments(), vec![("hi Kappa there", None)]);
+    }
+}
@@ -0,0 +1,372 @@
+use chat_reader::{
+    expression::Expression,
+    highlights::HighlightOptions,
+    output::{self, Format},
+    query::Query,
+    tools::{build_filter_with, format_time, is_valid_username, parse_time},
+    Error, Platform, Result,
+};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+/// How many videos are read at the same time when `--concurrency` is not given, and in interactive mode
+pub(crate) const DEFAULT_CONCURRENCY: u16 = 8;
+
+/// Read and search through the chat of VODs on Twitch and AfreecaTV
+///
+/// Run without a command to be prompted for everything interactively
+#[derive(Parser)]
+#[command(name = "chat_reader", version)]
+pub(crate) struct Cli {
+    /// Save every HTTP exchange of the run to DIR
+    #[arg(long, global = true, value_name = "DIR", conflicts_with = "replay")]
+    pub(crate) record: Option<PathBuf>,
+    /// Answer every HTTP request from the exchanges saved to DIR by `--record`, without touching the network
+    #[arg(long, global = true, value_name = "DIR")]
+    pub(crate) replay: Option<PathBuf>,
+    /// Read the chat of videos from an archive in DIR, storing the chat of every video read in full there first
+    ///
+    /// DIR is `chat_reader` in the user's cache directory when it is not given.
+    /// Without `--archive`, chat that stops being read part way, such as by Ctrl-C or a network error, is still kept
+    /// in that directory until the next read of the video resumes it, but chat read in full is not stored
+    #[arg(long, global = true, value_name = "DIR", num_args = 0..=1, require_equals = true)]
+    pub(crate) archive: Option<Option<PathBuf>>,
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub(crate) enum Command {
+    /// Read chat from Twitch
+    #[command(subcommand)]
+    Twitch(TwitchCommand),
+    /// Read chat from AfreecaTV
+    #[command(subcommand)]
+    Afreeca(AfreecaCommand),
+    /// Read chat already saved to files, without any network calls
+    ///
+    /// Reads the JSON and NDJSON written by `--format`, archive directories, JSON saved by Twitch chat downloaders
+    /// and the chat XML of AfreecaTV
+    File {
+        /// The files or directories to read, or `-` for stdin
+        ///
+        /// The `--archive` directory is read when none are given, or else stdin
+        paths: Vec<PathBuf>,
+        #[command(flatten)]
+        options: ChatOptions,
+        #[command(flatten)]
+        export: ExportOptions,
+    },
+    /// Find who said something and when in the archived chat of a channel, from its index
+    ///
+    /// A channel is indexed whenever it is read with `--archive`, which stores the chat of its videos
+    Search {
+        #[arg(value_enum)]
+        platform: PlatformArg,
+        /// The channel on Twitch, or the ID of the blog's owner on AfreecaTV
+        channel: String,
+        /// The words that a message must all have, ignoring case
+        ///
+        /// `word*` matches any word starting with `word`, and `"two words"` matches the words next to each other
+        search: String,
+        /// List the badges of each user, such as `subscriber/12`, before their name in text
+        #[arg(long)]
+        badges: bool,
+        /// How the found messages are written to stdout
+        #[arg(long, value_enum, default_value_t = FormatArg::Text)]
+        format: FormatArg,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub(crate) enum PlatformArg {
+    Twitch,
+    #[value(alias = "afreecatv")]
+    Afreeca,
+}
+
+impl From<PlatformArg> for Platform {
+    fn from(platform: PlatformArg) -> Self {
+        match platform {
+            PlatformArg::Twitch => Platform::Twitch,
+            PlatformArg::Afreeca => Platform::AfreecaTV,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub(crate) enum TwitchCommand {
+    /// Print the M3U8 playlist and the chat of a single VOD
+    Vod {
+        /// The numeric ID of the VOD, as in `twitch.tv/videos/<ID>`
+        id: u32,
+        #[command(flatten)]
+        options: ChatOptions,
+        #[command(flatten)]
+        export: ExportOptions,
+    },
+    /// Print the chat of every VOD of a channel
+    Channel {
+        #[arg(value_parser = parse_channel_name)]
+        name: String,
+        #[command(flatten)]
+        options: ChatOptions,
+    },
+    /// Print every clip of a channel whose title matches the filter
+    Clips {
+        #[arg(value_parser = parse_channel_name)]
+        name: String,
+        /// Only print clips whose title matches REGEX, ignoring case
+        #[arg(short, long, value_name = "REGEX")]
+        filter: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub(crate) enum AfreecaCommand {
+    /// Print the chat of a single video
+    Video {
+        /// The link to the video, such as `https://vod.afreecatv.com/PLAYER/STATION/<ID>`
+        url: String,
+        #[command(flatten)]
+        options: ChatOptions,
+        #[command(flatten)]
+        export: ExportOptions,
+    },
+    /// Print the chat of every video of a blog
+    Blog {
+        /// The ID of the blog's owner
+        name: String,
+        #[command(flatten)]
+        options: ChatOptions,
+    },
+}
+
+/// The options shared by every command that prints chat
+#[derive(Args)]
+pub(crate) struct ChatOptions {
+    /// Only print messages whose body matches REGEX, ignoring case
+    #[arg(short, long, value_name = "REGEX")]
+    pub(crate) filter: Option<String>,
+    /// Treat the filter as a literal string instead of a regular expression
+    #[arg(short = 'F', long, requires = "filter")]
+    pub(crate) fixed_strings: bool,
+    /// Match the filter with case, instead of ignoring it
+    #[arg(short = 's', long, requires = "filter")]
+    pub(crate) case_sensitive: bool,
+    /// Only print messages made of nothing but emotes
+    #[arg(long)]
+    pub(crate) emote_only: bool,
+    /// Print the messages that do not match instead
+    #[arg(short = 'v', long)]
+    pub(crate) invert_match: bool,
+    /// Stop reading the chat of a video after N matches
+    #[arg(short = 'm', long, value_name = "N")]
+    pub(crate) max_count: Option<usize>,
+    /// Print N messages after each match
+    #[arg(short = 'A', long, value_name = "N")]
+    pub(crate) after_context: Option<usize>,
+    /// Print N messages before each match
+    #[arg(short = 'B', long, value_name = "N")]
+    pub(crate) before_context: Option<usize>,
+    /// Print N messages before and after each match
+    #[arg(short = 'C', long, value_name = "N")]
+    pub(crate) context: Option<usize>,
+    /// Only print messages that satisfy EXPR, such as `badge = moderator and body ~ ban`
+    ///
+    /// Predicates on user, login, name, color, badge, bits, notice, emote, body, length and offset can be combined with and, or, not and parentheses
+    #[arg(short = 'w', long = "where", value_name = "EXPR")]
+    pub(crate) expression: Option<String>,
+    /// Only read chat from TIME into the video, as `HH:MM:SS`, `MM:SS` or seconds
+    #[arg(long, value_name = "TIME", value_parser = parse_time_arg)]
+    pub(crate) from: Option<u32>,
+    /// Stop reading chat at TIME into the video, as `HH:MM:SS`, `MM:SS` or seconds
+    #[arg(long, value_name = "TIME", value_parser = parse_time_arg)]
+    pub(crate) to: Option<u32>,
+    /// Print statistics about the matched messages instead of the messages themselves
+    ///
+    /// Counts messages, unique chatters, top chatters and the busiest minutes, with a sparkline of the activity over each video
+    #[arg(long, conflicts_with = "highlights")]
+    pub(crate) stats: bool,
+    /// Seconds of chat whose favorite emotes are listed together by `--stats`
+    #[arg(long, value_name = "SECS", default_value_t = 600.0, value_parser = parse_duration, requires = "stats")]
+    pub(crate) trend_window: f64,
+    #[command(flatten)]
+    pub(crate) highlights: HighlightArgs,
+    /// How many top chatters and busiest minutes are listed by `--stats`, and how many moments by `--highlights`
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    pub(crate) top: usize,
+    /// List the badges of each user, such as `subscriber/12`, before their name in text
+    #[arg(long)]
+    pub(crate) badges: bool,
+    /// How the matched messages are written to stdout
+    #[arg(long, value_enum, default_value_t = FormatArg::Text)]
+    pub(crate) format: FormatArg,
+    /// The maximum number of videos whose chat is read at the same time
+    #[arg(short = 'j', long, value_name = "N", default_value_t = DEFAULT_CONCURRENCY, value_parser = clap::value_parser!(u16).range(1..))]
+    pub(crate) concurrency: u16,
+}
+
+/// The options of `--highlights`
+#[derive(Args)]
+pub(crate) struct HighlightArgs {
+    /// Print the moments where the rate of matched messages spiked well above its rolling baseline, busiest first
+    ///
+    /// Filter on keywords, such as `-f "LUL|KEKW|clip it"`, to look for spikes in a keyword rate instead of in the whole chat
+    #[arg(long)]
+    pub(crate) highlights: bool,
+    /// Seconds of chat whose matched messages are counted together by `--highlights`
+    #[arg(long, value_name = "SECS", default_value_t = 30.0, value_parser = parse_duration, requires = "highlights")]
+    pub(crate) spike_window: f64,
+    /// Seconds of chat before each window that its baseline is averaged over
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value_t = 300.0,
+        requires = "highlights"
+    )]
+    pub(crate) baseline: f64,
+    /// How many times its baseline a window must reach to be a highlight
+    #[arg(long, value_name = "X", default_value_t = 3.0, requires = "highlights")]
+    pub(crate) threshold: f64,
+    /// The fewest matched messages a window must have to be a highlight
+    #[arg(long, value_name = "N", default_value_t = 5, requires = "highlights")]
+    pub(crate) min_messages: usize,
+}
+
+impl From<&HighlightArgs> for HighlightOptions {
+    fn from(args: &HighlightArgs) -> Self {
+        HighlightOptions {
+            window: args.spike_window,
+            baseline: args.baseline,
+            threshold: args.threshold,
+            min_messages: args.min_messages,
+        }
+    }
+}
+
+/// The options of the commands that can export the chat of a single video to a file
+#[derive(Args)]
+pub(crate) struct ExportOptions {
+    /// Write the matched messages to a file in FORMAT instead of printing them
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub(crate) export: Option<ExportFormat>,
+    /// The file to export to, `<ID>.<FORMAT>` by default
+    #[arg(short, long, value_name = "FILE", requires = "export")]
+    pub(crate) output: Option<PathBuf>,
+    /// Seconds that each message stays on screen in subtitles
+    #[arg(long, value_name = "SECS", default_value_t = 5.0, value_parser = parse_duration)]
+    pub(crate) duration: f64,
+    /// The most messages on screen at once in subtitles
+    #[arg(long, value_name = "N", default_value_t = 5, value_parser = clap::value_parser!(u16).range(1..))]
+    pub(crate) max_lines: u16,
+    /// Leave users' names uncolored in ASS subtitles
+    #[arg(long)]
+    pub(crate) no_user_colors: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub(crate) enum ExportFormat {
+    /// SubRip subtitles
+    Srt,
+    /// WebVTT subtitles
+    Vtt,
+    /// Advanced SubStation Alpha subtitles, with each user's name in their chat color
+    Ass,
+    /// A self-contained, searchable HTML page with every message linked to its moment in the video
+    Html,
+}
+
+impl ChatOptions {
+    /// Returns the flag that replaces the printed messages with a report, such as `--stats`, if one was given
+    pub(crate) fn report_flag(&self) -> Option<&'static str> {
+        if self.stats {
+            Some("--stats")
+        } else if self.highlights.highlights {
+            Some("--highlights")
+        } else {
+            None
+        }
+    }
+
+    /// Sets the output format of the run and returns the query that chat is searched with
+    pub(crate) fn prepare(&self) -> Result<Query> {
+        if self.stats && matches!(self.format, FormatArg::Csv) {
+            return Err(Error::Parse(
+                "--format csv with --stats, which can only be written as text, json or ndjson"
+                    .to_owned(),
+            ));
+        }
+        output::set_format(self.format.into());
+        output::set_show_badges(self.badges);
+        let filter = build_filter_with(
+            self.filter.as_ref(),
+            self.fixed_strings,
+            self.case_sensitive,
+        )?;
+        let from = self.from.unwrap_or(0);
+        if let Some(to) = self.to.filter(|to| *to <= from) {
+            return Err(Error::Parse(format!(
+                "--to {}, which must come after --from {}",
+                format_time(to),
+                format_time(from)
+            )));
+        }
+        let mut query = Query::new(filter).between(from.into(), self.to.map(f64::from));
+        if let Some(expression) = &self.expression {
+            query = query.matching(Expression::parse(expression)?)
+        }
+        query.emote_only = self.emote_only;
+        query.invert = self.invert_match;
+        query.max_count = self.max_count;
+        query.before_context = self.before_context.or(self.context).unwrap_or(0);
+        query.after_context = self.after_context.or(self.context).unwrap_or(0);
+        Ok(query)
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub(crate) enum FormatArg {
+    /// `[HH:MM:SS][user]: body`, colored when stdout is a terminal
+    Text,
+    /// A single JSON array of every message
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    /// A header row followed by one row per message
+    Csv,
+}
+
+impl From<FormatArg> for Format {
+    fn from(format: FormatArg) -> Self {
+        match format {
+            FormatArg::Text => Format::Text,
+            FormatArg::Json => Format::Json,
+            FormatArg::Ndjson => Format::Ndjson,
+            FormatArg::Csv => Format::Csv,
+        }
+    }
+}
+
+fn parse_time_arg(time: &str) -> std::result::Result<u32, String> {
+    parse_time(time).map_err(|e| e.to_string())
+}
+
+fn parse_duration(duration: &str) -> std::result::Result<f64, String> {
+    match duration.parse::<f64>() {
+        Ok(duration) if duration > 0.0 && duration.is_finite() => Ok(duration),
+        Ok(_) => Err(format!(
+            "'{}' is not a positive number of seconds",
+            duration
+        )),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_channel_name(name: &str) -> std::result::Result<String, String> {
+    if is_valid_username(name) {
+        Ok(name.to_owned())
+    } else {
+        Err(format!("'{}' is an invalid channel name", name))
+    }
+}
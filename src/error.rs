@@ -0,0 +1,104 @@
+use std::{
+    error,
+    fmt::{self, Display},
+    io,
+    num::{ParseFloatError, ParseIntError},
+};
+
+/// Every way that reading chat from a platform can fail
+#[derive(Debug)]
+pub enum Error {
+    /// The request could not be sent, or the response could not be read
+    Network(reqwest::Error),
+    /// The platform answered with an unsuccessful HTTP status
+    Status { url: String, status: u16 },
+    /// A response did not have the shape that was expected, usually because the platform changed its API
+    Schema(String),
+    /// The requested channel, video or clip does not exist
+    NotFound(String),
+    /// The platform will not serve the requested resource without being logged in
+    AuthRequired(String),
+    /// A value, either from the user or from a response, could not be parsed
+    Parse(String),
+    /// A local file, such as a recording, could not be read or written
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Returns `true` if the same request may succeed when it is sent again
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Network(_) => true,
+            Error::Status { status, .. } => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Network(e) => write!(f, "network error: {}", e),
+            Error::Status { url, status } => write!(f, "{} responded with HTTP {}", url, status),
+            Error::Schema(message) => write!(f, "unexpected response: {}", message),
+            Error::NotFound(message) => write!(f, "{} could not be found", message),
+            Error::AuthRequired(message) => write!(f, "{} requires logging in", message),
+            Error::Parse(message) => write!(f, "could not parse {}", message),
+            Error::Io(e) => write!(f, "file error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Network(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Network(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(e: csv::Error) -> Self {
+        Error::Io(e.into())
+    }
+}
+
+impl From<ParseIntError> for Error {
+    fn from(e: ParseIntError) -> Self {
+        Error::Parse(e.to_string())
+    }
+}
+
+impl From<ParseFloatError> for Error {
+    fn from(e: ParseFloatError) -> Self {
+        Error::Parse(e.to_string())
+    }
+}
+
+impl From<regex::Error> for Error {
+    fn from(e: regex::Error) -> Self {
+        Error::Parse(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Schema(e.to_string())
+    }
+}
@@ -0,0 +1,53 @@
+use crate::cli::{ExportFormat, ExportOptions};
+use chat_reader::{
+    html::write_html,
+    output,
+    query::Query,
+    subtitles::{write_subtitles, SubtitleFormat, SubtitleOptions},
+    Result, VideoSource,
+};
+use std::{fs::File, io::BufWriter};
+
+/// Writes every message of `video` that matches `query` to a file in `format`
+pub(crate) fn export<V: VideoSource>(
+    video: &V,
+    query: &Query,
+    format: ExportFormat,
+    options: &ExportOptions,
+) -> Result<()> {
+    let (subtitle_format, extension) = match format {
+        ExportFormat::Srt => (Some(SubtitleFormat::Srt), "srt"),
+        ExportFormat::Vtt => (Some(SubtitleFormat::WebVtt), "vtt"),
+        ExportFormat::Ass => (Some(SubtitleFormat::Ass), "ass"),
+        ExportFormat::Html => (None, "html"),
+    };
+    let path = match &options.output {
+        Some(path) => path.to_owned(),
+        None => format!("{}.{}", video.id(), extension).into(),
+    };
+    let messages = query
+        .select(query.read(video))
+        .map(|selected| selected.map(|selected| selected.message));
+    let writer = BufWriter::new(File::create(&path)?);
+    let written = match subtitle_format {
+        Some(subtitle_format) => write_subtitles(
+            messages,
+            subtitle_format,
+            &SubtitleOptions {
+                duration: options.duration,
+                max_lines: options.max_lines.into(),
+                user_colors: !options.no_user_colors,
+            },
+            writer,
+        )?,
+        None => write_html(video, messages, writer)?,
+    };
+    output::write_info(format!(
+        "Exported {} messages of {} v{} to {}",
+        written,
+        video.title(),
+        video.id(),
+        path.display()
+    ));
+    Ok(())
+}
@@ -0,0 +1,489 @@
+//! Filter expressions over every attribute of a `ChatMessage`, not just its body
+//!
+//! An expression is made of predicates such as `badge = moderator`, `user = xqc`, `color = #FF0000`, `emote = Kappa`,
+//! `notice = subgift`, `bits >= 100`, `length > 100` or `body ~ ban`, combined with `and`, `or`, `not` and parentheses:
+//!
+//! `badge = moderator and (body ~ "ban" or body ~ timeout)`
+//!
+//! `=` and `!=` compare text ignoring case, `~` and `!~` match a case insensitive regular expression,
+//! and `<`, `<=`, `>` and `>=` compare numbers. `offset` is also compared with times written as `HH:MM:SS` or `MM:SS`
+use crate::{
+    chat::ChatMessage,
+    error::{Error, Result},
+    tools::parse_time,
+};
+use regex::Regex;
+use std::{borrow::Cow, fmt, iter::Peekable, str::Chars};
+
+/// The attributes of a `ChatMessage` that predicates can look at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    /// Either the login or the display name of the user
+    User,
+    Login,
+    /// The display name of the user
+    Name,
+    /// The `#RRGGBB` chat color of the user, or `none`
+    Color,
+    /// Any badge of the user, either by name, such as `moderator`, or by name and version, such as `subscriber/12`
+    Badge,
+    /// The bits cheered with the message
+    Bits,
+    /// The kind of user notice the message is, such as `sub`, `resub`, `subgift` or `submysterygift`, or `none`
+    Notice,
+    /// The name of any emote in the body, such as `Kappa`
+    Emote,
+    Body,
+    /// The number of characters of the body
+    Length,
+    /// Seconds since the start of the video, compared with either a number of seconds or a time such as `1:20:00`
+    Offset,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Field> {
+        Some(match name.to_lowercase().as_str() {
+            "user" => Field::User,
+            "login" => Field::Login,
+            "name" | "display_name" => Field::Name,
+            "color" => Field::Color,
+            "badge" | "badges" => Field::Badge,
+            "bits" => Field::Bits,
+            "notice" => Field::Notice,
+            "emote" | "emotes" => Field::Emote,
+            "body" | "message" | "text" => Field::Body,
+            "length" | "len" => Field::Length,
+            "offset" | "time" => Field::Offset,
+            _ => return None,
+        })
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, Field::Bits | Field::Length | Field::Offset)
+    }
+
+    /// Returns the text values of the field in `message`, of which any may satisfy a predicate
+    fn values<'a>(&self, message: &'a ChatMessage) -> Vec<Cow<'a, str>> {
+        match self {
+            Field::User => vec![
+                Cow::from(message.login.as_str()),
+                Cow::from(message.display_name.as_str()),
+            ],
+            Field::Login => vec![Cow::from(message.login.as_str())],
+            Field::Name => vec![Cow::from(message.display_name.as_str())],
+            Field::Color => vec![Cow::from(message.color.as_deref().unwrap_or("none"))],
+            Field::Badge => message
+                .badges
+                .iter()
+                .flat_map(|badge| [Cow::from(badge.name.as_str()), Cow::from(badge.to_string())])
+                .collect(),
+            Field::Notice => vec![Cow::from(message.notice.as_deref().unwrap_or("none"))],
+            Field::Emote => message
+                .emotes
+                .iter()
+                .map(|emote| Cow::from(emote.name.as_str()))
+                .collect(),
+            Field::Body => vec![Cow::from(message.body.as_str())],
+            Field::Bits | Field::Length | Field::Offset => Vec::new(),
+        }
+    }
+
+    fn number(&self, message: &ChatMessage) -> f64 {
+        match self {
+            Field::Bits => f64::from(message.bits),
+            Field::Length => message.body.chars().count() as f64,
+            Field::Offset => message.offset,
+            _ => 0.0,
+        }
+    }
+}
+
+/// How a predicate compares a field to its value
+#[derive(Clone, Debug)]
+pub enum Comparison {
+    Equal(String),
+    NotEqual(String),
+    Matches(Regex),
+    NotMatches(Regex),
+    EqualNumber(f64),
+    Less(f64),
+    LessOrEqual(f64),
+    Greater(f64),
+    GreaterOrEqual(f64),
+}
+
+/// A parsed filter expression, see the module documentation for its syntax
+#[derive(Clone, Debug)]
+pub enum Expression {
+    Predicate(Field, Comparison),
+    Not(Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    /// Parses `expression`, returning an `Error::Parse` that points out the problem if it is invalid
+    pub fn parse<S: AsRef<str>>(expression: S) -> Result<Self> {
+        let expression = expression.as_ref();
+        let error = |message: String| {
+            Error::Parse(format!(
+                "'{}' as a filter expression: {}",
+                expression, message
+            ))
+        };
+        let tokens = tokenize(expression).map_err(error)?;
+        let mut parser = Parser { tokens, index: 0 };
+        let parsed = parser.or().map_err(error)?;
+        match parser.tokens.get(parser.index) {
+            None => Ok(parsed),
+            Some(token) => Err(error(format!("unexpected {}", token))),
+        }
+    }
+
+    /// Returns `true` if `message` satisfies the expression
+    pub fn matches(&self, message: &ChatMessage) -> bool {
+        match self {
+            Expression::Predicate(field, comparison) => compare(*field, comparison, message),
+            Expression::Not(expression) => !expression.matches(message),
+            Expression::And(left, right) => left.matches(message) && right.matches(message),
+            Expression::Or(left, right) => left.matches(message) || right.matches(message),
+        }
+    }
+}
+
+fn compare(field: Field, comparison: &Comparison, message: &ChatMessage) -> bool {
+    let values = field.values(message);
+    let number = field.number(message);
+    match comparison {
+        Comparison::Equal(value) => values.iter().any(|v| v.eq_ignore_ascii_case(value)),
+        Comparison::NotEqual(value) => !values.iter().any(|v| v.eq_ignore_ascii_case(value)),
+        Comparison::Matches(re) => values.iter().any(|v| re.is_match(v)),
+        Comparison::NotMatches(re) => !values.iter().any(|v| re.is_match(v)),
+        Comparison::EqualNumber(value) => number == *value,
+        Comparison::Less(value) => number < *value,
+        Comparison::LessOrEqual(value) => number <= *value,
+        Comparison::Greater(value) => number > *value,
+        Comparison::GreaterOrEqual(value) => number >= *value,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// A bare word or a quoted string
+    Word(String, bool),
+    Operator(&'static str),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word, _) => write!(f, "'{}'", word),
+            Token::Operator(operator) => write!(f, "'{}'", operator),
+            Token::And => write!(f, "'and'"),
+            Token::Or => write!(f, "'or'"),
+            Token::Not => write!(f, "'not'"),
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+        }
+    }
+}
+
+/// Every operator, with the longer ones first so that `<=` is not read as `<`
+const OPERATORS: [&str; 12] = [
+    "!=", "!~", "<=", ">=", "==", "&&", "||", "=", "~", "<", ">", "!",
+];
+
+fn tokenize(expression: &str) -> std::result::Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' {
+            chars.next();
+            tokens.push(Token::Open)
+        } else if c == ')' {
+            chars.next();
+            tokens.push(Token::Close)
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            tokens.push(Token::Word(quoted(&mut chars, c)?, true))
+        } else if let Some(operator) = operator(&mut chars) {
+            tokens.push(match operator {
+                "&&" => Token::And,
+                "||" => Token::Or,
+                "!" => Token::Not,
+                "==" => Token::Operator("="),
+                operator => Token::Operator(operator),
+            })
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || "()!=~<>&|\"'".contains(c) {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            if word.is_empty() {
+                return Err(format!("unexpected '{}'", c));
+            }
+            tokens.push(match word.to_lowercase().as_str() {
+                "and" => Token::And,
+                "or" => Token::Or,
+                "not" => Token::Not,
+                _ => Token::Word(word, false),
+            })
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads the operator at the start of `chars`, if there is one
+fn operator(chars: &mut Peekable<Chars>) -> Option<&'static str> {
+    let rest: String = chars.clone().take(2).collect();
+    let operator = OPERATORS
+        .iter()
+        .find(|operator| rest.starts_with(*operator))?;
+    for _ in 0..operator.len() {
+        chars.next();
+    }
+    Some(operator)
+}
+
+/// Reads a string up to the closing `quote`, where `\` escapes the next character
+fn quoted(chars: &mut Peekable<Chars>, quote: char) -> std::result::Result<String, String> {
+    let mut word = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c) => word.push(c),
+                None => break,
+            },
+            c if c == quote => return Ok(word),
+            c => word.push(c),
+        }
+    }
+    Err(format!("missing closing {}", quote))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+type ParseResult = std::result::Result<Expression, String>;
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.index).cloned();
+        self.index += 1;
+        token
+    }
+
+    fn or(&mut self) -> ParseResult {
+        let mut left = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            left = Expression::Or(Box::new(left), Box::new(self.and()?))
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> ParseResult {
+        let mut left = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            left = Expression::And(Box::new(left), Box::new(self.not()?))
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> ParseResult {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(Expression::Not(Box::new(self.not()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> ParseResult {
+        match self.next() {
+            Some(Token::Open) => {
+                let expression = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expression),
+                    Some(token) => Err(format!("expected ')' but found {}", token)),
+                    None => Err("missing closing ')'".to_owned()),
+                }
+            }
+            Some(Token::Word(name, false)) => self.predicate(&name),
+            Some(token) => Err(format!("expected a field but found {}", token)),
+            None => Err("expected a field but the expression ended".to_owned()),
+        }
+    }
+
+    fn predicate(&mut self, name: &str) -> ParseResult {
+        let field = Field::parse(name).ok_or_else(|| {
+            format!(
+                "unknown field '{}', expected one of user, login, name, color, badge, bits, notice, emote, body, length, offset",
+                name
+            )
+        })?;
+        let operator = match self.next() {
+            Some(Token::Operator(operator)) => operator,
+            Some(token) => {
+                return Err(format!(
+                    "expected an operator after '{}' but found {}",
+                    name, token
+                ))
+            }
+            None => return Err(format!("expected an operator after '{}'", name)),
+        };
+        let value = match self.next() {
+            Some(Token::Word(value, _)) => value,
+            Some(token) => {
+                return Err(format!(
+                    "expected a value after '{}' but found {}",
+                    operator, token
+                ))
+            }
+            None => return Err(format!("expected a value after '{}'", operator)),
+        };
+        let regex = || Regex::new(&format!("(?i){}", value)).map_err(|e| e.to_string());
+        let number = || {
+            match value.parse::<f64>() {
+            Ok(number) => Ok(number),
+            //offsets are also written as times, as with --from and --to
+            Err(_) if field == Field::Offset => parse_time(&value)
+                .map(f64::from)
+                .map_err(|_| {
+                    format!(
+                        "'{}' is compared with '{}', so it must be a time such as 1:20:00 or a number of seconds",
+                        name, value
+                    )
+                }),
+            Err(_) => Err(format!(
+                "'{}' is compared with '{}', so it must be a number",
+                name, value
+            )),
+        }
+        };
+        let comparison = match operator {
+            "=" if field.is_numeric() => Comparison::EqualNumber(number()?),
+            "!=" if field.is_numeric() => {
+                let equal = Expression::Predicate(field, Comparison::EqualNumber(number()?));
+                return Ok(Expression::Not(Box::new(equal)));
+            }
+            "=" => Comparison::Equal(value),
+            "!=" => Comparison::NotEqual(value),
+            "~" | "!~" if field.is_numeric() => {
+                return Err(format!(
+                    "'{}' can only be compared with =, !=, <, <=, > and >=",
+                    name
+                ))
+            }
+            "~" => Comparison::Matches(regex()?),
+            "!~" => Comparison::NotMatches(regex()?),
+            _ if !field.is_numeric() => {
+                return Err(format!(
+                    "'{}' can only be compared with =, !=, ~ and !~",
+                    name
+                ))
+            }
+            "<" => Comparison::Less(number()?),
+            "<=" => Comparison::LessOrEqual(number()?),
+            ">" => Comparison::Greater(number()?),
+            _ => Comparison::GreaterOrEqual(number()?),
+        };
+        Ok(Expression::Predicate(field, comparison))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::{tests::message, Badge};
+
+    fn matches(expression: &str, message: &ChatMessage) -> bool {
+        Expression::parse(expression).unwrap().matches(message)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let alice = message(10.0, "alice", "ban him");
+        assert!(matches("user = bob and body ~ ban or user = alice", &alice));
+        assert!(!matches(
+            "user = bob and (body ~ ban or user = alice)",
+            &alice
+        ));
+        assert!(matches("not user = bob && !(body !~ BAN)", &alice));
+    }
+
+    #[test]
+    fn text_is_compared_ignoring_case() {
+        let mut moderator = message(10.0, "Alice", "Hello there");
+        moderator.badges = vec![Badge {
+            name: "subscriber".to_owned(),
+            version: "12".to_owned(),
+        }];
+        assert!(matches("name = ALICE and login = alice", &moderator));
+        assert!(matches(
+            "badge = subscriber and badge = Subscriber/12",
+            &moderator
+        ));
+        assert!(matches("body = 'hello there'", &moderator));
+        assert!(matches("color = none and notice = none", &moderator));
+        assert!(!matches("badge != subscriber", &moderator));
+    }
+
+    #[test]
+    fn numbers_are_compared() {
+        let mut cheer = message(90.5, "alice", "four");
+        cheer.bits = 100;
+        assert!(matches("bits >= 100 and bits < 101 and bits = 100", &cheer));
+        assert!(matches("length = 4 and length != 5", &cheer));
+        assert!(matches("offset > 90 and offset <= 90.5", &cheer));
+    }
+
+    #[test]
+    fn offsets_are_compared_with_times() {
+        let late = message(4800.0, "alice", "hi");
+        assert!(matches("offset >= 1:20:00", &late));
+        assert!(!matches("offset > 1:20:00", &late));
+        assert!(matches("time < 80:01", &late));
+        assert!(Expression::parse("bits > 1:00").is_err());
+    }
+
+    #[test]
+    fn invalid_expressions_are_rejected() {
+        for expression in [
+            "",
+            "user",
+            "user =",
+            "size = 1",
+            "(user = a",
+            "user = a)",
+            "user < a",
+            "bits ~ 1",
+            "body = \"open",
+            "body ~ (",
+            "user = a and",
+        ] {
+            assert!(
+                matches!(Expression::parse(expression), Err(Error::Parse(_))),
+                "{}",
+                expression
+            )
+        }
+    }
+}
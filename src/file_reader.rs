@@ -0,0 +1,86 @@
+use crate::{
+    cli::{ChatOptions, ExportOptions},
+    export, report, retry_once_then_skip,
+};
+use chat_reader::{
+    archive::Archive, saved::read_paths, source::print_videos, Error, Result, VideoSource,
+};
+use std::path::PathBuf;
+
+/// Runs the `file` command, reading chat saved in `paths`, or else in `archive` or on stdin
+pub(crate) fn run(
+    paths: Vec<PathBuf>,
+    archive: Option<Archive>,
+    options: ChatOptions,
+    export: ExportOptions,
+) -> Result<()> {
+    let query = options.prepare()?;
+    if let (Some(flag), Some(_)) = (options.report_flag(), export.export) {
+        return Err(Error::Parse(format!(
+            "{} with --export, which cannot be used together",
+            flag
+        )));
+    }
+    let paths = match (paths.is_empty(), archive) {
+        (false, _) => paths,
+        (true, Some(archive)) => vec![archive.dir().to_owned()],
+        (true, None) => vec![PathBuf::from("-")],
+    };
+    let name = paths
+        .iter()
+        .map(|path| match path.to_str() {
+            Some("-") => "stdin".to_owned(),
+            _ => path.display().to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut videos = read_paths(&paths)?;
+    if videos.len() == 1 {
+        let video = videos.remove(0);
+        if let Some(format) = export.export {
+            return export::export(&video, &query, format, &export);
+        }
+        if options.stats {
+            return report::stats(&video, &query, options.trend_window, options.top);
+        }
+        if options.highlights.highlights {
+            let highlights = (&options.highlights).into();
+            return report::highlights(&video, &query, &highlights, options.top);
+        }
+        return video.print_chat_blocking(&query);
+    }
+    if videos.is_empty() {
+        return Err(Error::NotFound(format!("chat in {}", name)));
+    }
+    if export.export.is_some() {
+        return Err(Error::Parse(format!(
+            "--export with the chat of {} videos, which can only export a single video",
+            videos.len()
+        )));
+    }
+    if options.stats {
+        return report::channel_stats(
+            &name,
+            videos,
+            &query,
+            options.trend_window,
+            options.top,
+            options.concurrency.into(),
+        );
+    }
+    if options.highlights.highlights {
+        return report::channel_highlights(
+            videos,
+            &query,
+            &(&options.highlights).into(),
+            options.top,
+            options.concurrency.into(),
+        );
+    }
+    print_videos(
+        videos,
+        &query,
+        options.concurrency.into(),
+        retry_once_then_skip(),
+    )
+}
@@ -0,0 +1,159 @@
+//! `Transport`s that record every HTTP exchange of a run to a directory, and serve them back later
+//!
+//! Every exchange is stored as its own pretty printed JSON file named after a hash of the request,
+//! so recordings can be checked in and diffed when a platform changes the shape of its responses
+use crate::{
+    error::{Error, Result},
+    http::{HttpRequest, HttpResponse, Method, Transport},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// A single recorded exchange, as it is stored on disk
+#[derive(Serialize, Deserialize)]
+struct Fixture {
+    method: String,
+    url: String,
+    body: Option<String>,
+    status: u16,
+    /// The body of the response, stored as JSON when it is JSON so that it stays readable
+    response: Value,
+}
+
+/// Returns the name shared by every fixture of `request`, without the index of the repetition
+///
+/// Headers are left out, as they do not change between runs
+fn fixture_key(request: &HttpRequest) -> String {
+    //64 bit FNV-1a, which unlike `DefaultHasher` is guaranteed to be stable between Rust releases
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let method = method_name(request.method);
+    let body = request.body.as_deref().unwrap_or_default();
+    for byte in [method, "\n", &request.url, "\n", body]
+        .iter()
+        .flat_map(|part| part.bytes())
+    {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+fn method_name(method: Method) -> &'static str {
+    match method {
+        Method::Get => "GET",
+        Method::Post => "POST",
+    }
+}
+
+fn fixture_path(dir: &Path, key: &str, repetition: usize) -> PathBuf {
+    dir.join(format!("{}-{}.json", key, repetition))
+}
+
+/// Returns how many times `key` has been seen before, counting this time
+fn next_repetition(counters: &Mutex<HashMap<String, usize>>, key: &str) -> usize {
+    let mut counters = counters.lock().unwrap();
+    let counter = counters.entry(key.to_owned()).or_insert(0);
+    *counter += 1;
+    *counter - 1
+}
+
+/// A `Transport` that sends every request with another `Transport` and saves each exchange to a directory
+pub struct RecordingTransport<T: Transport> {
+    inner: T,
+    dir: PathBuf,
+    counters: Mutex<HashMap<String, usize>>,
+}
+
+impl<T: Transport> RecordingTransport<T> {
+    /// Creates a new `RecordingTransport` that records to `dir`, creating it if it does not exist
+    pub fn new<P: AsRef<Path>>(inner: T, dir: P) -> Result<Self> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(RecordingTransport {
+            inner,
+            dir: dir.as_ref().to_owned(),
+            counters: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+impl<T: Transport> Transport for RecordingTransport<T> {
+    fn execute(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        let response = self.inner.execute(request)?;
+        let key = fixture_key(request);
+        let repetition = next_repetition(&self.counters, &key);
+        let fixture = Fixture {
+            method: method_name(request.method).to_owned(),
+            url: request.url.to_owned(),
+            body: request.body.to_owned(),
+            status: response.status,
+            response: match serde_json::from_str(&response.body) {
+                Ok(json) => json,
+                Err(_) => Value::String(response.body.to_owned()),
+            },
+        };
+        let json = serde_json::to_string_pretty(&fixture)?;
+        fs::write(fixture_path(&self.dir, &key, repetition), json)?;
+        Ok(response)
+    }
+}
+
+/// A `Transport` that never touches the network, and instead answers with the exchanges saved by a `RecordingTransport`
+///
+/// Repeated requests are answered in the order they were recorded, and once the recordings of a request run out
+/// the last one is served again
+pub struct ReplayTransport {
+    dir: PathBuf,
+    counters: Mutex<HashMap<String, usize>>,
+}
+
+impl ReplayTransport {
+    /// Creates a new `ReplayTransport` that replays the recordings in `dir`
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        if !dir.as_ref().is_dir() {
+            return Err(Error::NotFound(format!(
+                "the recording directory {}",
+                dir.as_ref().display()
+            )));
+        }
+        Ok(ReplayTransport {
+            dir: dir.as_ref().to_owned(),
+            counters: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn execute(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        let key = fixture_key(request);
+        let repetition = next_repetition(&self.counters, &key);
+        let path = (0..=repetition)
+            .rev()
+            .map(|repetition| fixture_path(&self.dir, &key, repetition))
+            .find(|path| path.is_file());
+        let path = match path {
+            Some(path) => path,
+            None => {
+                return Err(Error::NotFound(format!(
+                    "a recorded response to {} {} in {}",
+                    method_name(request.method),
+                    request.url,
+                    self.dir.display()
+                )))
+            }
+        };
+        let fixture: Fixture = serde_json::from_str(&fs::read_to_string(path)?)?;
+        Ok(HttpResponse {
+            status: fixture.status,
+            body: match fixture.response {
+                Value::String(body) => body,
+                json => json.to_string(),
+            },
+        })
+    }
+}
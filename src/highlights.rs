@@ -0,0 +1,249 @@
+//! Finds the moments of a video where chat suddenly got much busier than usual, which are often worth clipping
+//!
+//! The matched messages are counted in windows of a few seconds, and a window spikes when it has several times
+//! as many messages as the rolling baseline: the average of the windows before it.
+//! Filtering on keywords such as `LUL|KEKW|clip it` turns the message rate into a keyword rate
+use crate::{error::Result, output, query::Query, source::VideoSource, tools::format_time};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// How `find_spikes()` decides which windows spike
+#[derive(Clone, Copy, Debug)]
+pub struct HighlightOptions {
+    /// Seconds of chat counted together
+    pub window: f64,
+    /// Seconds of chat before a window that its baseline is averaged over
+    pub baseline: f64,
+    /// How many times its baseline a window must reach to spike
+    pub threshold: f64,
+    /// The fewest messages a window must have to spike, so that quiet chats do not spike on a handful of messages
+    pub min_messages: usize,
+}
+
+impl Default for HighlightOptions {
+    fn default() -> Self {
+        HighlightOptions {
+            window: 30.0,
+            baseline: 300.0,
+            threshold: 3.0,
+            min_messages: 5,
+        }
+    }
+}
+
+/// A run of consecutive windows that spiked
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spike {
+    /// Seconds into the video at which the first window starts
+    pub start: f64,
+    /// Seconds into the video at which the last window ends
+    pub end: f64,
+    pub messages: usize,
+    /// The baseline of the busiest window, in messages per window
+    pub baseline: f64,
+    /// How many times its baseline the busiest window reached
+    pub score: f64,
+}
+
+/// A ranked `Spike` of a video, as it is written by the machine readable formats
+#[derive(Serialize, Clone, Debug)]
+pub struct Highlight {
+    pub rank: usize,
+    pub video_id: String,
+    pub video_title: String,
+    pub start: f64,
+    pub end: f64,
+    /// `start` as `HH:MM:SS`
+    pub timestamp: String,
+    pub messages: usize,
+    pub baseline: f64,
+    pub score: f64,
+    /// A link to the video on its platform's website, starting at `start`
+    pub link: String,
+    /// The playlist of the video, when it could be found
+    pub playlist: Option<String>,
+}
+
+/// Returns the spikes in the rate of the messages sent at `offsets`, busiest compared to their baseline first
+///
+/// Windows are counted from `origin`, the first second of the video that was read.
+/// Windows without any messages never spike, but count as empty windows in the baselines of the others
+pub fn find_spikes<I: IntoIterator<Item = f64>>(
+    offsets: I,
+    origin: f64,
+    options: &HighlightOptions,
+) -> Vec<Spike> {
+    let window = options.window.max(1.0);
+    //only the windows that have messages are kept, however far into the video they are
+    let mut counts: BTreeMap<u64, usize> = BTreeMap::new();
+    for offset in offsets {
+        let index = ((offset - origin).max(0.0) / window) as u64;
+        *counts.entry(index).or_insert(0) += 1
+    }
+    let windows = match counts.keys().next_back() {
+        Some(last) => last.saturating_add(1),
+        None => return Vec::new(),
+    };
+    let span = ((options.baseline / window).round() as u64).max(1);
+    let mut spikes: Vec<Spike> = Vec::new();
+    let mut last_spiked = None;
+    for (index, count) in counts.iter().map(|(index, count)| (*index, *count)) {
+        //windows near the start do not have enough before them, so the baseline is topped up with the ones after
+        let before = index.saturating_sub(span)..index;
+        let next = index.saturating_add(1);
+        let after = next..span.saturating_add(1).min(windows).max(next);
+        let around = (before.end - before.start) + (after.end - after.start);
+        let baseline = if around == 0 {
+            0.0
+        } else {
+            let messages: usize = counts
+                .range(before)
+                .chain(counts.range(after))
+                .map(|(_, count)| count)
+                .sum();
+            messages as f64 / around as f64
+        };
+        let score = count as f64 / baseline.max(1.0);
+        if count < options.min_messages || score < options.threshold {
+            continue;
+        }
+        let start = origin + index as f64 * window;
+        let end = start + window;
+        match spikes.last_mut() {
+            Some(spike) if last_spiked == Some(index.wrapping_sub(1)) => {
+                spike.end = end;
+                spike.messages += count;
+                if score > spike.score {
+                    spike.score = score;
+                    spike.baseline = baseline
+                }
+            }
+            _ => spikes.push(Spike {
+                start,
+                end,
+                messages: count,
+                baseline,
+                score,
+            }),
+        }
+        last_spiked = Some(index)
+    }
+    spikes.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then(b.messages.cmp(&a.messages))
+    });
+    spikes
+}
+
+/// Reads the chat of `video` within the range of `query`, returning the `top` spikes in the rate of matched messages
+///
+/// Each highlight links to its moment of the video, along with the playlist of the video when it could be found
+pub fn video_highlights<V: VideoSource>(
+    video: &V,
+    query: &Query,
+    options: &HighlightOptions,
+    top: usize,
+) -> Result<Vec<Highlight>> {
+    let mut offsets = Vec::new();
+    for selected in query.select(query.read(video)) {
+        let selected = selected?;
+        if !selected.is_context {
+            offsets.push(selected.message.offset)
+        }
+    }
+    let spikes = find_spikes(offsets, query.from, options);
+    let playlist = if spikes.is_empty() {
+        None
+    } else {
+        video.playback_url().ok()
+    };
+    let (video_id, video_title) = (video.id(), video.title());
+    Ok(spikes
+        .into_iter()
+        .take(top)
+        .enumerate()
+        .map(|(rank, spike)| Highlight {
+            rank: rank + 1,
+            video_id: video_id.to_owned(),
+            video_title: video_title.to_owned(),
+            start: spike.start,
+            end: spike.end,
+            timestamp: format_time(spike.start as u32),
+            messages: spike.messages,
+            baseline: spike.baseline,
+            score: spike.score,
+            link: video.link_at(spike.start),
+            playlist: playlist.to_owned(),
+        })
+        .collect())
+}
+
+/// Writes the highlights of a single video in the current `output::Format`
+///
+/// In `Format::Text`, the title and playlist of the video are followed by one highlight per line
+pub fn write_highlights(highlights: &[Highlight]) -> Result<()> {
+    if output::format() == output::Format::Text {
+        if let Some(highlight) = highlights.first() {
+            println!("\n{} v{}", highlight.video_title, highlight.video_id);
+            if let Some(playlist) = &highlight.playlist {
+                println!("{}", playlist)
+            }
+        }
+    }
+    for highlight in highlights {
+        output::write_record(highlight, || {
+            println!(
+                "  {:>2}. [{}] {} messages, {:.1}x the baseline of {:.1}  {}",
+                highlight.rank,
+                highlight.timestamp,
+                highlight.messages,
+                highlight.score,
+                highlight.baseline,
+                highlight.link
+            )
+        })?
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> HighlightOptions {
+        HighlightOptions {
+            window: 10.0,
+            baseline: 50.0,
+            threshold: 3.0,
+            min_messages: 3,
+        }
+    }
+
+    /// Returns one message a window for 20 windows, with `burst` more messages in the window starting at `at`
+    fn offsets(at: f64, burst: usize) -> Vec<f64> {
+        let mut offsets: Vec<f64> = (0..20).map(|window| window as f64 * 10.0).collect();
+        offsets.extend((0..burst).map(|_| at + 5.0));
+        offsets
+    }
+
+    #[test]
+    fn bursts_spike_over_their_baseline() {
+        let spikes = find_spikes(offsets(100.0, 9), 0.0, &options());
+        assert_eq!(spikes.len(), 1);
+        assert_eq!((spikes[0].start, spikes[0].end), (100.0, 110.0));
+        assert_eq!((spikes[0].messages, spikes[0].baseline), (10, 1.0));
+        assert!(find_spikes(offsets(100.0, 1), 0.0, &options()).is_empty());
+    }
+
+    #[test]
+    fn windows_far_into_the_video_are_counted_without_the_ones_between() {
+        let mut far = offsets(100.0, 9);
+        far.extend([1e15; 5].iter().copied());
+        far.push(f64::INFINITY);
+        let spikes = find_spikes(far, 0.0, &options());
+        assert_eq!(spikes.len(), 2);
+        assert_eq!(spikes[0].messages, 10);
+        assert_eq!((spikes[1].start, spikes[1].messages), (1e15, 5));
+    }
+}
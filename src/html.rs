@@ -0,0 +1,127 @@
+//! Turns the chat of a video into a single, self-contained HTML page that can be shared and searched without this tool
+use crate::{chat::ChatMessage, error::Result, source::VideoSource};
+use std::io::Write;
+
+const STYLE: &str = "body{margin:0;background:#18181b;color:#efeff1;font:14px/1.5 sans-serif}\
+header{position:sticky;top:0;padding:12px 16px;background:#0e0e10;border-bottom:1px solid #2f2f35}\
+h1{margin:0 0 8px;font-size:18px}a{color:#bf94ff}\
+#search{width:100%;max-width:480px;padding:6px 8px;border:1px solid #2f2f35;border-radius:4px;background:#18181b;color:inherit}\
+#count{margin-left:8px;color:#adadb8}\
+#chat{padding:8px 16px}.m{padding:2px 0;word-wrap:break-word}.t{color:#adadb8;text-decoration:none;font-family:monospace}\
+.u{font-weight:bold}.n{background:#1f1925}.g{color:#f0c674}.e{height:1.75em;vertical-align:middle}.hidden{display:none}";
+
+//Searches are case insensitive regular expressions, falling back to plain text when the expression is invalid
+//Emotes are searched by their names
+const SCRIPT: &str = "const search=document.getElementById('search');\
+const count=document.getElementById('count');\
+const messages=Array.from(document.querySelectorAll('.m'));\
+const texts=messages.map(m=>{let text='';m.childNodes.forEach(n=>{text+=n.alt||n.textContent});return text});\
+function update(){const q=search.value;let re=null;\
+try{re=new RegExp(q,'i')}catch(e){}\
+const lower=q.toLowerCase();let shown=0;\
+messages.forEach((m,i)=>{const text=texts[i];\
+const match=q===''||(re?re.test(text):text.toLowerCase().includes(lower));\
+m.classList.toggle('hidden',!match);if(match)shown++});\
+count.textContent=shown+' / '+messages.length+' messages'}\
+search.addEventListener('input',update);update();";
+
+/// Writes `messages` from `video` to `writer` as an HTML page, returning how many messages were written
+///
+/// Every message is linked to the moment it was sent on the platform's website, users' names are shown in their chat color
+/// with their badges on hover, user notices and bits are tagged, and emotes are shown as their images
+pub fn write_html<V, I, W>(video: &V, messages: I, mut writer: W) -> Result<usize>
+where
+    V: VideoSource,
+    I: IntoIterator<Item = Result<ChatMessage>>,
+    W: Write,
+{
+    let title = escape(&video.title());
+    write!(
+        writer,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{title}</title>\n<style>{style}</style>\n</head>\n<body>\n\
+         <header>\n<h1>{title}</h1>\n\
+         <div><a href=\"{link}\">{platform} v{id}</a></div>\n\
+         <input id=\"search\" type=\"search\" placeholder=\"Search chat (RegExp)\" autofocus><span id=\"count\"></span>\n\
+         </header>\n<div id=\"chat\">\n",
+        title = title,
+        style = STYLE,
+        link = escape(&video.link_at(0.0)),
+        platform = video.platform(),
+        id = escape(&video.id()),
+    )?;
+    let mut written = 0;
+    for message in messages {
+        let message = message?;
+        let (r, g, b) = message.rgb();
+        let badges: Vec<String> = message
+            .badges
+            .iter()
+            .map(|badge| badge.to_string())
+            .collect();
+        let title = if badges.is_empty() {
+            String::new()
+        } else {
+            format!(" title=\"{}\"", escape(&badges.join(" ")))
+        };
+        let mut tags = String::new();
+        if let Some(notice) = &message.notice {
+            tags.push_str(&format!(" <span class=\"g\">[{}]</span>", escape(notice)))
+        }
+        if message.bits > 0 {
+            tags.push_str(&format!(
+                " <span class=\"g\">[{} bits]</span>",
+                message.bits
+            ))
+        }
+        writeln!(
+            writer,
+            "<div class=\"m{}\"><a class=\"t\" href=\"{}\">[{}]</a> \
+             <span class=\"u\" style=\"color:#{:02x}{:02x}{:02x}\"{}>{}</span>{}: {}</div>",
+            if message.notice.is_some() { " n" } else { "" },
+            escape(&video.link_at(message.offset)),
+            message.timestamp(),
+            r,
+            g,
+            b,
+            title,
+            escape(&message.display_name),
+            tags,
+            body_html(&message)
+        )?;
+        written += 1
+    }
+    write!(
+        writer,
+        "</div>\n<script>{}</script>\n</body>\n</html>\n",
+        SCRIPT
+    )?;
+    writer.flush()?;
+    Ok(written)
+}
+
+/// Returns the escaped body of `message`, with its emotes as images
+fn body_html(message: &ChatMessage) -> String {
+    message
+        .fragments()
+        .into_iter()
+        .map(|(text, emote)| match emote {
+            Some(emote) if !emote.id.is_empty() => format!(
+                "<img class=\"e\" src=\"{}\" alt=\"{}\" title=\"{}\">",
+                escape(&emote.image_url()),
+                escape(text),
+                escape(text)
+            ),
+            _ => escape(text),
+        })
+        .collect()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
@@ -0,0 +1,157 @@
+//! The HTTP layer that every platform request goes through
+//!
+//! By default requests are sent to the real platforms with `reqwest`, but both the `Transport` that sends them
+//! and the `Endpoints` they are sent to can be replaced, such as to point the crate at a local mock server
+use crate::error::Result;
+use lazy_static::lazy_static;
+use reqwest::blocking::Client;
+use serde::Serialize;
+use std::sync::{Arc, RwLock};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+}
+
+/// A request to one of the platforms, independent of the `Transport` that sends it
+#[derive(Clone, Debug)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+}
+
+impl HttpRequest {
+    pub fn get<S: AsRef<str>>(url: S) -> Self {
+        HttpRequest {
+            method: Method::Get,
+            url: url.as_ref().to_owned(),
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    pub fn post<S: AsRef<str>>(url: S) -> Self {
+        HttpRequest {
+            method: Method::Post,
+            ..HttpRequest::get(url)
+        }
+    }
+
+    pub fn header<K: AsRef<str>, V: AsRef<str>>(mut self, key: K, value: V) -> Self {
+        self.headers
+            .push((key.as_ref().to_owned(), value.as_ref().to_owned()));
+        self
+    }
+
+    /// Sets the body of the request to `body` serialized as JSON
+    pub fn json<T: Serialize + ?Sized>(mut self, body: &T) -> Result<Self> {
+        self.body = Some(serde_json::to_string(body)?);
+        Ok(self.header("Content-Type", "application/json"))
+    }
+}
+
+/// The response to an `HttpRequest`, whatever its status
+#[derive(Clone, Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+/// Something that can send an `HttpRequest` and return its response
+///
+/// Implementations should only return an `Err` when no response was received at all;
+/// unsuccessful statuses are turned into errors by `tools::send()`
+pub trait Transport: Send + Sync {
+    fn execute(&self, request: &HttpRequest) -> Result<HttpResponse>;
+}
+
+/// The default `Transport`, which sends every request over the network
+#[derive(Default)]
+pub struct ReqwestTransport {
+    client: Client,
+}
+
+impl Transport for ReqwestTransport {
+    fn execute(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        let mut builder = match request.method {
+            Method::Get => self.client.get(&request.url),
+            Method::Post => self.client.post(&request.url),
+        };
+        for (key, value) in &request.headers {
+            builder = builder.header(key, value)
+        }
+        if let Some(body) = &request.body {
+            builder = builder.body(body.to_owned())
+        }
+        let response = builder.send()?;
+        Ok(HttpResponse {
+            status: response.status().as_u16(),
+            body: response.text()?,
+        })
+    }
+}
+
+/// The base URLs of every service the platforms are read from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Endpoints {
+    /// The full URL that Twitch GQL operations are posted to
+    pub twitch_gql: String,
+    pub afreeca_bjapi: String,
+    pub afreeca_stbbs: String,
+    pub afreeca_videoimg: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Endpoints {
+            twitch_gql: "https://gql.twitch.tv/gql".to_owned(),
+            afreeca_bjapi: "https://bjapi.afreecatv.com".to_owned(),
+            afreeca_stbbs: "https://stbbs.afreecatv.com".to_owned(),
+            afreeca_videoimg: "https://videoimg.afreecatv.com".to_owned(),
+        }
+    }
+}
+
+impl Endpoints {
+    /// Returns `Endpoints` that send every request to a single server at `base_url`, such as `http://127.0.0.1:8080`
+    ///
+    /// The paths of the requests are left untouched, so Twitch GQL operations are posted to `{base_url}/gql`
+    pub fn single_host<S: AsRef<str>>(base_url: S) -> Self {
+        let base_url = base_url.as_ref().trim_end_matches('/');
+        Endpoints {
+            twitch_gql: format!("{}/gql", base_url),
+            afreeca_bjapi: base_url.to_owned(),
+            afreeca_stbbs: base_url.to_owned(),
+            afreeca_videoimg: base_url.to_owned(),
+        }
+    }
+}
+
+lazy_static! {
+    static ref TRANSPORT: RwLock<Arc<dyn Transport>> =
+        RwLock::new(Arc::new(ReqwestTransport::default()));
+    static ref ENDPOINTS: RwLock<Arc<Endpoints>> = RwLock::new(Arc::new(Endpoints::default()));
+}
+
+/// Replaces the `Transport` that every following request is sent with
+pub fn set_transport<T: Transport + 'static>(transport: T) {
+    *TRANSPORT.write().unwrap() = Arc::new(transport)
+}
+
+/// Returns the `Transport` that requests are currently sent with
+pub fn transport() -> Arc<dyn Transport> {
+    TRANSPORT.read().unwrap().clone()
+}
+
+/// Replaces the `Endpoints` that every following request is sent to
+pub fn set_endpoints(endpoints: Endpoints) {
+    *ENDPOINTS.write().unwrap() = Arc::new(endpoints)
+}
+
+/// Returns the `Endpoints` that requests are currently sent to
+pub fn endpoints() -> Arc<Endpoints> {
+    ENDPOINTS.read().unwrap().clone()
+}
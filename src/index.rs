@@ -0,0 +1,612 @@
+//! An inverted index over the archived chat of a channel, so that finding who said something and when does not scan every message
+//!
+//! Each lowercased word of a message body points at the message and the position in it that it was used at.
+//! The index of a channel is stored next to the archive in the directory `{dir}/{platform}/{channel}.index`,
+//! as one `Segment` per video that is never written again, so `sync()` only has to add the videos archived since it last ran
+use crate::{
+    archive::Archive,
+    chat::{ChatMessage, Platform},
+    error::{Error, Result},
+    source::VideoSource,
+    tools::error,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::PathBuf,
+};
+
+/// A video whose chat is in a `ChatIndex`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedVideo {
+    pub id: String,
+    pub title: String,
+}
+
+/// Words that must follow each other in a message, the last of which may only be the start of a word
+///
+/// A term without any words matches no message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Term {
+    pub words: Vec<String>,
+    /// `true` if the last word matches any word that starts with it
+    pub prefix: bool,
+}
+
+/// Parses a search into the terms that a message must all match
+///
+/// Words are matched anywhere in the message, `word*` matches any word starting with `word`,
+/// and `"two words"` matches the words next to each other
+pub fn parse_search(search: &str) -> Result<Vec<Term>> {
+    let mut terms = Vec::new();
+    for (index, part) in search.split('"').enumerate() {
+        //parts between quotes are phrases, any other part is a list of words
+        let phrases = if index % 2 == 1 {
+            vec![part]
+        } else {
+            part.split_whitespace().collect()
+        };
+        for phrase in phrases {
+            let words = tokenize(phrase);
+            if !words.is_empty() {
+                terms.push(Term {
+                    words,
+                    prefix: phrase.trim_end().ends_with('*'),
+                })
+            }
+        }
+    }
+    if search.matches('"').count() % 2 == 1 {
+        return Err(Error::Parse(format!(
+            "'{}', which has an unclosed quote",
+            search
+        )));
+    }
+    if terms.is_empty() {
+        return Err(Error::Parse(format!(
+            "'{}', which has no words to search for",
+            search
+        )));
+    }
+    Ok(terms)
+}
+
+/// Splits `text` into the lowercased words that are indexed
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// A message found by `ChatIndex::search()`
+#[derive(Clone, Debug)]
+pub struct Hit {
+    pub video: IndexedVideo,
+    /// The position of the message in the archived chat of the video
+    pub message: u32,
+    pub offset: f64,
+    /// The display name of the user that sent the message
+    pub user: String,
+}
+
+/// The first line of a stored `Segment`
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    #[serde(flatten)]
+    video: IndexedVideo,
+    /// The display name of every user that sent a message
+    users: Vec<String>,
+    /// The offset and the position in `users` of every message, in the order they are archived
+    messages: Vec<(f64, u32)>,
+}
+
+/// The index of the chat of a single video
+///
+/// It is stored as `{id}.words`: a JSON `Header`, followed by a line for each word with every use of it as `message:position`.
+/// Words are sorted, and only the lines of the words searched for are parsed
+#[derive(Debug)]
+pub struct Segment {
+    header: Header,
+    words: BTreeMap<String, Vec<(u32, u32)>>,
+}
+
+impl Segment {
+    /// Indexes every message of the video `id`, which must be read in the same order as it is archived
+    pub fn new<I: IntoIterator<Item = Result<ChatMessage>>>(
+        id: &str,
+        title: &str,
+        messages: I,
+    ) -> Result<Self> {
+        let mut users = Vec::new();
+        let mut user_positions: HashMap<String, u32> = HashMap::new();
+        let mut offsets = Vec::new();
+        let mut words: BTreeMap<String, Vec<(u32, u32)>> = BTreeMap::new();
+        for (message, chat_message) in (0..).zip(messages) {
+            let chat_message = chat_message?;
+            let user = match user_positions.get(&chat_message.display_name) {
+                Some(user) => *user,
+                None => {
+                    let user = users.len() as u32;
+                    users.push(chat_message.display_name.to_owned());
+                    user_positions.insert(chat_message.display_name.to_owned(), user);
+                    user
+                }
+            };
+            offsets.push((chat_message.offset, user));
+            for (position, word) in (0..).zip(tokenize(&chat_message.body)) {
+                words.entry(word).or_default().push((message, position))
+            }
+        }
+        Ok(Segment {
+            header: Header {
+                video: IndexedVideo {
+                    id: id.to_owned(),
+                    title: title.to_owned(),
+                },
+                users,
+                messages: offsets,
+            },
+            words,
+        })
+    }
+
+    /// Writes the segment to `writer` in the form that `search_segment()` reads
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        serde_json::to_writer(&mut writer, &self.header)?;
+        writeln!(writer)?;
+        for (word, uses) in &self.words {
+            write!(writer, "{}", word)?;
+            for (message, position) in uses {
+                write!(writer, " {}:{}", message, position)?
+            }
+            writeln!(writer)?
+        }
+        Ok(())
+    }
+}
+
+/// Returns every message of the segment read from `reader` that matches all of `terms`, in the order they were sent
+pub fn search_segment<R: BufRead>(reader: R, terms: &[Term]) -> Result<Vec<Hit>> {
+    let mut lines = reader.lines();
+    let header = match lines.next() {
+        Some(header) => header?,
+        None => return Ok(Vec::new()),
+    };
+    //every word looked up, and whether any word starting with it is looked up
+    let mut wanted: Vec<(&str, bool)> = terms
+        .iter()
+        .flat_map(|term| {
+            let last = term.words.len().saturating_sub(1);
+            (0..)
+                .zip(&term.words)
+                .map(move |(index, word)| (word.as_str(), term.prefix && index == last))
+        })
+        .collect();
+    wanted.sort_unstable();
+    wanted.dedup();
+    let mut uses: HashMap<(&str, bool), Vec<(u32, u32)>> = HashMap::new();
+    for line in lines {
+        let line = line?;
+        let (word, positions) = line.split_once(' ').unwrap_or((&line, ""));
+        //the lines are sorted by word, so none are left to find once every wanted word has been passed
+        if wanted
+            .iter()
+            .all(|(wanted, prefix)| word > *wanted && !(*prefix && word.starts_with(wanted)))
+        {
+            break;
+        }
+        let keys: Vec<&(&str, bool)> = wanted
+            .iter()
+            .filter(|(wanted, prefix)| word == *wanted || (*prefix && word.starts_with(wanted)))
+            .collect();
+        if keys.is_empty() {
+            continue;
+        }
+        let parsed = parse_uses(positions)?;
+        for key in keys {
+            uses.entry(*key).or_default().extend(&parsed)
+        }
+    }
+    let mut messages: Option<HashSet<u32>> = None;
+    for term in terms {
+        let found = term_messages(term, &uses);
+        messages = Some(match messages {
+            Some(messages) => messages.intersection(&found).copied().collect(),
+            None => found,
+        })
+    }
+    let mut messages: Vec<u32> = messages.unwrap_or_default().into_iter().collect();
+    if messages.is_empty() {
+        return Ok(Vec::new());
+    }
+    messages.sort_unstable();
+    let header: Header = serde_json::from_str(&header)?;
+    let mut hits = Vec::with_capacity(messages.len());
+    for message in messages {
+        let user = header
+            .messages
+            .get(message as usize)
+            .and_then(|(offset, user)| Some((*offset, header.users.get(*user as usize)?)));
+        match user {
+            Some((offset, user)) => hits.push(Hit {
+                video: header.video.to_owned(),
+                message,
+                offset,
+                user: user.to_owned(),
+            }),
+            None => {
+                return Err(Error::Parse(format!(
+                    "message {} of v{}, which is not in the header of its index",
+                    message, header.video.id
+                )))
+            }
+        }
+    }
+    Ok(hits)
+}
+
+/// Parses the `message:position` uses of a word in a stored `Segment`
+fn parse_uses(positions: &str) -> Result<Vec<(u32, u32)>> {
+    positions
+        .split_whitespace()
+        .map(|position| match position.split_once(':') {
+            Some((message, position)) => Ok((message.parse()?, position.parse()?)),
+            None => Err(Error::Parse(format!("'{}' as message:position", position))),
+        })
+        .collect()
+}
+
+/// Returns every message that `term` is used in, given the `uses` of its words
+fn term_messages(term: &Term, uses: &HashMap<(&str, bool), Vec<(u32, u32)>>) -> HashSet<u32> {
+    let last = match term.words.len().checked_sub(1) {
+        Some(last) => last,
+        None => return HashSet::new(),
+    };
+    let uses_of = |index: usize| {
+        uses.get(&(term.words[index].as_str(), term.prefix && index == last))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    };
+    let following: Vec<HashSet<(u32, u32)>> = (1..=last)
+        .map(|index| uses_of(index).iter().copied().collect())
+        .collect();
+    uses_of(0)
+        .iter()
+        .filter(|(message, position)| {
+            (1..)
+                .zip(&following)
+                .all(|(distance, positions)| positions.contains(&(*message, position + distance)))
+        })
+        .map(|(message, _)| *message)
+        .collect()
+}
+
+/// The index of the archived chat of a channel, a directory with a `Segment` for each video
+#[derive(Clone, Debug)]
+pub struct ChatIndex {
+    dir: PathBuf,
+}
+
+impl ChatIndex {
+    /// Opens the index of `channel` on `platform` stored in `archive`, which has no videos until some are added
+    pub fn open(archive: &Archive, platform: Platform, channel: &str) -> Self {
+        ChatIndex {
+            dir: archive.index_path(platform, channel),
+        }
+    }
+
+    fn segment_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.words", id))
+    }
+
+    /// Returns the ID of every video whose chat is in the index
+    pub fn videos(&self) -> Result<Vec<String>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut videos = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "words")
+            {
+                if let Some(id) = path.file_stem() {
+                    videos.push(id.to_string_lossy().into_owned())
+                }
+            }
+        }
+        videos.sort();
+        Ok(videos)
+    }
+
+    /// Returns `true` if the chat of the video `id` is in the index
+    pub fn contains(&self, id: &str) -> bool {
+        self.segment_path(id).is_file()
+    }
+
+    /// Adds every message of the video `id` to the index, which must be read in the same order as it is archived
+    ///
+    /// The video is only added once all of its messages could be read, and the rest of the index is left as it is
+    pub fn add<I: IntoIterator<Item = Result<ChatMessage>>>(
+        &self,
+        id: &str,
+        title: &str,
+        messages: I,
+    ) -> Result<()> {
+        let segment = Segment::new(id, title, messages)?;
+        fs::create_dir_all(&self.dir)?;
+        let path = self.segment_path(id);
+        //written in full before taking its place, so that a segment is never left half written
+        let partial = path.with_extension("words.partial");
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        segment.write(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(partial, path)?;
+        Ok(())
+    }
+
+    /// Returns every message that matches all of `terms`, sorted by the time they were sent
+    ///
+    /// Videos are sorted by their ID, which grows with every video published on both Twitch and AfreecaTV
+    pub fn search(&self, terms: &[Term]) -> Result<Vec<Hit>> {
+        let mut hits = Vec::new();
+        for id in self.videos()? {
+            let path = self.segment_path(&id);
+            let file = BufReader::new(File::open(&path)?);
+            match search_segment(file, terms) {
+                Ok(found) => hits.extend(found),
+                Err(Error::Io(e)) => return Err(Error::Io(e)),
+                Err(e) => {
+                    return Err(Error::Parse(format!(
+                        "the chat index in {}: {}",
+                        path.display(),
+                        e
+                    )))
+                }
+            }
+        }
+        hits.sort_by(|a, b| {
+            let id = |hit: &Hit| hit.video.id.parse::<u64>().unwrap_or(u64::MAX);
+            id(a)
+                .cmp(&id(b))
+                .then_with(|| a.video.id.cmp(&b.video.id))
+                .then(a.offset.total_cmp(&b.offset))
+                .then(a.message.cmp(&b.message))
+        });
+        Ok(hits)
+    }
+}
+
+/// Adds every video of `videos` whose chat is archived but not yet indexed to the index of `channel`, returning how many were added
+///
+/// A video whose archived chat cannot be read is reported and left out, to be indexed by a later sync
+pub fn sync<V: VideoSource>(archive: &Archive, channel: &str, videos: &[V]) -> Result<usize> {
+    let platform = match videos.first() {
+        Some(video) => video.platform(),
+        None => return Ok(0),
+    };
+    let index = ChatIndex::open(archive, platform, channel);
+    let mut added = 0;
+    for video in videos {
+        let id = video.id();
+        if index.contains(&id) || !archive.contains(platform, &id) {
+            continue;
+        }
+        let result = archive
+            .read(platform, &id)
+            .and_then(|messages| index.add(&id, &video.title(), messages));
+        match result {
+            Ok(()) => added += 1,
+            Err(e) => error(format!(
+                "Could not index the chat of {} v{}: {}\n",
+                video.title(),
+                id,
+                e
+            )),
+        }
+    }
+    Ok(added)
+}
+
+/// Reads the messages of `hits` on `platform` from `archive`, in the same order as `hits`
+pub fn read_hits(archive: &Archive, platform: Platform, hits: &[Hit]) -> Result<Vec<ChatMessage>> {
+    let mut messages = Vec::new();
+    let mut start = 0;
+    while start < hits.len() {
+        let video = &hits[start].video;
+        let end = start
+            + hits[start..]
+                .iter()
+                .take_while(|hit| &hit.video == video)
+                .count();
+        let mut wanted: HashMap<u32, usize> = HashMap::new();
+        for (position, hit) in hits[start..end].iter().enumerate() {
+            wanted.insert(hit.message, position);
+        }
+        let mut found: Vec<Option<ChatMessage>> = vec![None; end - start];
+        let last = wanted.keys().copied().max().unwrap_or_default();
+        for (line, message) in (0..=last).zip(archive.read(platform, &video.id)?) {
+            if let Some(position) = wanted.get(&line) {
+                found[*position] = Some(message?)
+            }
+        }
+        for message in found {
+            match message {
+                Some(message) => messages.push(message),
+                None => {
+                    return Err(Error::Schema(format!(
+                        "the archived chat of {} v{} no longer has the messages of its index",
+                        video.title, video.id
+                    )))
+                }
+            }
+        }
+        start = end
+    }
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::tests::message;
+    use std::{env, io::Cursor};
+
+    fn segment(id: &str, bodies: &[(&str, &str)]) -> Segment {
+        let messages = (0..)
+            .zip(bodies)
+            .map(|(offset, (name, body))| Ok(message(f64::from(offset) * 10.0, name, body)));
+        Segment::new(id, "Test VOD", messages).unwrap()
+    }
+
+    /// Returns the position, offset and user of every message of `segment` that matches `search`
+    fn search(segment: &Segment, search: &str) -> Vec<(u32, f64, String)> {
+        let mut stored = Vec::new();
+        segment.write(&mut stored).unwrap();
+        search_segment(Cursor::new(stored), &parse_search(search).unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|hit| (hit.message, hit.offset, hit.user))
+            .collect()
+    }
+
+    fn chat() -> Segment {
+        segment(
+            "1",
+            &[
+                ("alice", "Hello there"),
+                ("Bob", "there, hello!"),
+                ("alice", "helicopter HELLO there"),
+                ("carol", "nothing to see"),
+            ],
+        )
+    }
+
+    #[test]
+    fn searches_are_split_into_terms() {
+        let terms = parse_search(r#"hello "Over there" wor*"#).unwrap();
+        let terms: Vec<(Vec<&str>, bool)> = terms
+            .iter()
+            .map(|term| (term.words.iter().map(String::as_str).collect(), term.prefix))
+            .collect();
+        assert_eq!(
+            terms,
+            vec![
+                (vec!["hello"], false),
+                (vec!["over", "there"], false),
+                (vec!["wor"], true)
+            ]
+        );
+        assert!(parse_search("\"unclosed").is_err());
+        assert!(parse_search("  !? ").is_err());
+    }
+
+    #[test]
+    fn every_word_must_be_in_the_message() {
+        let segment = chat();
+        assert_eq!(
+            search(&segment, "hello"),
+            vec![
+                (0, 0.0, "alice".to_owned()),
+                (1, 10.0, "Bob".to_owned()),
+                (2, 20.0, "alice".to_owned())
+            ]
+        );
+        assert_eq!(search(&segment, "THERE hello").len(), 3);
+        assert_eq!(search(&segment, "hello see"), Vec::new());
+        assert_eq!(search(&segment, "missing"), Vec::new());
+    }
+
+    #[test]
+    fn phrases_must_be_in_order() {
+        let segment = chat();
+        assert_eq!(search(&segment, "\"hello there\"").len(), 2);
+        assert_eq!(search(&segment, "\"there hello\"")[0].0, 1);
+        assert_eq!(search(&segment, "\"hello to\""), Vec::new());
+    }
+
+    #[test]
+    fn terms_without_words_match_nothing() {
+        let mut stored = Vec::new();
+        chat().write(&mut stored).unwrap();
+        let empty = Term {
+            words: Vec::new(),
+            prefix: true,
+        };
+        let hits = search_segment(Cursor::new(stored), &[empty]).unwrap();
+        assert!(hits.is_empty());
+    }
+
+    #[test]
+    fn words_after_the_last_wanted_are_not_read() {
+        let mut stored = Vec::new();
+        chat().write(&mut stored).unwrap();
+        //a line that cannot be parsed is only an error if it is read
+        stored.extend(b"zzz not:positions\n");
+        let terms = parse_search("hel*").unwrap();
+        assert_eq!(
+            search_segment(Cursor::new(&stored), &terms).unwrap().len(),
+            3
+        );
+        let terms = parse_search("zzz").unwrap();
+        assert!(search_segment(Cursor::new(&stored), &terms).is_err());
+    }
+
+    #[test]
+    fn prefixes_match_the_start_of_words() {
+        let segment = chat();
+        let messages = |query| -> Vec<u32> {
+            search(&segment, query)
+                .into_iter()
+                .map(|hit| hit.0)
+                .collect()
+        };
+        assert_eq!(messages("heli*"), vec![2]);
+        assert_eq!(messages("hel*"), vec![0, 1, 2]);
+        assert_eq!(messages("\"helicopter hel*\""), vec![2]);
+        assert_eq!(messages("\"hel* there\""), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn videos_are_added_to_the_index_without_touching_the_others() {
+        let dir = env::temp_dir().join(format!("chat_reader_index_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let index = ChatIndex::open(&Archive::new(&dir), Platform::Twitch, "TestChannel");
+        assert_eq!(index.videos().unwrap(), Vec::<String>::new());
+        let add = |id: &str, body: &str| {
+            index
+                .add(id, "Test VOD", vec![Ok(message(5.0, "alice", body))])
+                .unwrap()
+        };
+        add("20", "hello from the second");
+        add("3", "hello from the first");
+        let first = fs::read(index.segment_path("3")).unwrap();
+        add("100", "goodbye");
+        assert_eq!(fs::read(index.segment_path("3")).unwrap(), first);
+        assert!(index.contains("100") && !index.contains("4"));
+        assert_eq!(index.videos().unwrap().len(), 3);
+        //videos are in the order they were published, not in the order their names sort in
+        let hits: Vec<String> = index
+            .search(&parse_search("hello").unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|hit| hit.video.id)
+            .collect();
+        assert_eq!(hits, vec!["3", "20"]);
+        //a video that cannot be read whole is left out
+        let failed = index.add(
+            "5",
+            "",
+            vec![Err(Error::Parse("a broken message".to_owned()))],
+        );
+        assert!(failed.is_err() && !index.contains("5"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
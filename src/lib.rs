@@ -0,0 +1,48 @@
+//! Read and search through the chat of VODs on Twitch and AfreecaTV
+//!
+//! The binary shipped with this crate is a thin CLI over the types exported here,
+//! so everything it can do is also available to other Rust programs
+#[path = "afreecatv/afreecatv_channel.rs"]
+pub mod afreecatv_channel;
+#[path = "afreecatv/afreecatv_models.rs"]
+pub mod afreecatv_models;
+#[path = "afreecatv/afreecatv_video.rs"]
+pub mod afreecatv_video;
+#[path = "twitch/twitch_channel.rs"]
+pub mod twitch_channel;
+#[path = "twitch/twitch_clip.rs"]
+pub mod twitch_clip;
+#[path = "twitch/twitch_gql.rs"]
+pub mod twitch_gql;
+#[path = "twitch/twitch_models.rs"]
+pub mod twitch_models;
+#[path = "twitch/twitch_vod.rs"]
+pub mod twitch_vod;
+
+pub mod archive;
+pub mod chat;
+pub mod error;
+pub mod expression;
+pub mod fixtures;
+pub mod highlights;
+pub mod html;
+pub mod http;
+pub mod index;
+pub mod output;
+pub mod query;
+pub mod saved;
+pub mod source;
+pub mod stats;
+pub mod subtitles;
+pub mod tools;
+
+pub use crate::{
+    afreecatv_channel::Blog,
+    afreecatv_video::{AfreecaComments, AfreecaVideo},
+    chat::{Badge, ChatMessage, Emote, Platform},
+    error::{Error, Result},
+    query::Query,
+    source::{ChannelSource, ErrorAction, Resumable, VideoSource},
+    twitch_channel::TwitchChannel,
+    twitch_vod::{TwitchComments, TwitchVOD},
+};
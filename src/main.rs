@@ -1,69 +1,158 @@
-#[path = "afreecatv/afreecatv_channel.rs"]
-mod afreecatv_channel;
 #[path = "afreecatv/afreecatv_reader.rs"]
 mod afreecatv_reader;
-#[path = "afreecatv/afreecatv_video.rs"]
-mod afreecatv_video;
-#[path = "twitch/twitch_channel.rs"]
-mod twitch_channel;
-#[path = "twitch/twitch_clip.rs"]
-mod twitch_clip;
+mod cli;
+mod export;
+mod file_reader;
+mod report;
+mod search;
 #[path = "twitch/twitch_reader.rs"]
 mod twitch_reader;
-#[path = "twitch/twitch_vod.rs"]
-mod twitch_vod;
 
-mod tools;
-
-use crate::tools::error;
+use chat_reader::{
+    archive::{checkpoint_all, default_dir, set_archive, Archive},
+    fixtures::{RecordingTransport, ReplayTransport},
+    http::{set_transport, ReqwestTransport},
+    output,
+    tools::{build_filter, error},
+    Error, ErrorAction, Result, VideoSource,
+};
+use clap::Parser;
+use cli::{Cli, Command};
+use regex::Regex;
 use std::{
-    env::{args, Args},
+    collections::HashSet,
+    fmt::Display,
     io::{stdin, stdout, Write},
-    iter::Skip,
+    process,
 };
 
-fn main_args(mut args: Skip<Args>) {
-    while let Some(arg) = args.next() {
-        let arg = arg.as_ref();
-        match arg {
-            "-tc" => twitch_reader::args_channel(&mut args),
-            "-tv" => twitch_reader::args_vod(&mut args),
-            &_ => error(format!(
-                "'{}' was an unrecognized argument, expected [-tc, -tv]",
-                arg
-            )),
+/// Reads a line typed in answer to a prompt, without its line ending
+pub(crate) fn get_input() -> String {
+    let mut input = String::new();
+    if let Err(e) = stdout().flush() {
+        exit_error(e);
+    }
+    if let Err(e) = stdin().read_line(&mut input) {
+        exit_error(e);
+    }
+    input.trim_end_matches(&['\r', '\n'][..]).to_owned()
+}
+
+/// Asks for the filter that chat is searched with
+pub(crate) fn get_filter() -> Result<Regex> {
+    print!("(RegExp) Please enter a phrase you would like to search for >>> ");
+    build_filter(Some(get_input()))
+}
+
+/// Prints `message` as an error and exits
+pub(crate) fn exit_error<S: Display>(message: S) -> ! {
+    error(message);
+    process::exit(-1)
+}
+
+/// Decides what happens when the chat of one video of a channel cannot be read
+///
+/// Errors that may go away on their own are retried once, any other error skips the video
+pub(crate) fn retry_once_then_skip<V: VideoSource>() -> impl FnMut(&V, &Error) -> ErrorAction {
+    let mut retried = HashSet::new();
+    move |video, e| {
+        error(format!(
+            "Could not read the chat of {} v{}: {}\n",
+            video.title(),
+            video.id(),
+            e
+        ));
+        if e.is_transient() && retried.insert(video.id()) {
+            ErrorAction::Retry
+        } else {
+            ErrorAction::Skip
         }
     }
 }
 
 fn main() {
-    let args = args().skip(1);
-    if args.len() > 0 {
-        main_args(args)
-    } else {
-        interactive_main()
+    let cli = Cli::parse();
+    //whatever was read before Ctrl-C is written out, and kept to be resumed
+    let _ = ctrlc::set_handler(|| {
+        checkpoint_all();
+        output::finish();
+        process::exit(130)
+    });
+    if let Some(dir) = cli.record {
+        match RecordingTransport::new(ReqwestTransport::default(), dir) {
+            Ok(transport) => set_transport(transport),
+            Err(e) => exit_error(e),
+        }
+    }
+    let replaying = cli.replay.is_some();
+    if let Some(dir) = cli.replay {
+        match ReplayTransport::new(dir) {
+            Ok(transport) => set_transport(transport),
+            Err(e) => exit_error(e),
+        }
+    }
+    let archive = cli
+        .archive
+        .map(|dir| Archive::new(dir.unwrap_or_else(default_dir)));
+    //saved chat is never stored again, `file` only reads the archive
+    if !matches!(cli.command, Some(Command::File { .. })) {
+        //without `--archive`, chat that stops being read part way is still kept to be resumed, unless it is replayed
+        let checkpoints = (!replaying).then(|| Archive::checkpoints(default_dir()));
+        set_archive(archive.to_owned().or(checkpoints))
+    }
+    let result = match cli.command {
+        Some(Command::File {
+            paths,
+            options,
+            export,
+        }) => file_reader::run(paths, archive, options, export),
+        Some(Command::Search {
+            platform,
+            channel,
+            search,
+            badges,
+            format,
+        }) => {
+            let archive = archive.unwrap_or_else(|| Archive::new(default_dir()));
+            output::set_format(format.into());
+            output::set_show_badges(badges);
+            search::run(&archive, platform.into(), &channel, &search)
+        }
+        Some(Command::Twitch(command)) => twitch_reader::run(command),
+        Some(Command::Afreeca(command)) => afreecatv_reader::run(command),
+        None => {
+            interactive_main();
+            Ok(())
+        }
+    };
+    output::finish();
+    if let Err(e) = result {
+        exit_error(e)
     }
 }
 
 fn interactive_main() {
-    let mut platform_name = String::new();
-    print!("What platform would you link to pull from (Twitch, AfreecaTV)? >>> ");
-    stdout().flush().unwrap();
-    stdin().read_line(&mut platform_name).unwrap();
-    platform_name = platform_name
-        .trim_end_matches(&['\r', '\n'][..])
-        .to_lowercase();
-    let platform_name = platform_name.as_str();
+    loop {
+        let mut platform_name = String::new();
+        print!("What platform would you link to pull from (Twitch, AfreecaTV)? >>> ");
+        stdout().flush().unwrap();
+        stdin().read_line(&mut platform_name).unwrap();
+        platform_name = platform_name
+            .trim_end_matches(&['\r', '\n'][..])
+            .to_lowercase();
+        let platform_name = platform_name.as_str();
 
-    match platform_name {
-        "twitch" => twitch_reader::main(),
-        "afreecatv" => afreecatv_reader::main(),
-        _ => {
-            error(format!(
-                "\n'{}' was an unexpected response\nPlease choose between [Twitch, AfreecaTV]\n",
-                platform_name
-            ));
-            main()
+        match platform_name {
+            "twitch" => twitch_reader::main(),
+            "afreecatv" => afreecatv_reader::main(),
+            _ => {
+                error(format!(
+                    "\n'{}' was an unexpected response\nPlease choose between [Twitch, AfreecaTV]\n",
+                    platform_name
+                ));
+                continue;
+            }
         }
+        break;
     }
 }
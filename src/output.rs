@@ -0,0 +1,195 @@
+//! Where and how matched chat messages are written
+//!
+//! The format is shared by every thread that prints chat, so it is set once with `set_format()`,
+//! and `finish()` must be called once everything has been printed
+use crate::{chat::Platform, error::Result, query::Selected};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::{
+    fmt::Display,
+    io::{stderr, stdout, IsTerminal, Stdout, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+/// How matched chat messages are written to stdout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// `[HH:MM:SS][display_name]: body`, colored by the user's chat color when stdout is a terminal
+    Text,
+    /// A single JSON array of every message
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    /// A header row followed by one row per message
+    Csv,
+}
+
+/// The fields of a `ChatMessage` that are written by the machine readable formats
+#[derive(Serialize)]
+struct Record<'a> {
+    platform: Platform,
+    video_id: &'a str,
+    video_title: &'a str,
+    offset: f64,
+    user_id: &'a str,
+    login: &'a str,
+    display_name: &'a str,
+    color: Option<&'a str>,
+    /// The badges of the user as `name/version`, separated by spaces
+    badges: String,
+    bits: u32,
+    notice: Option<&'a str>,
+    body: &'a str,
+    /// The names of the emotes in the body, separated by spaces
+    emotes: String,
+    /// `true` if the message is only written as the context of a match
+    context: bool,
+}
+
+impl<'a> From<&'a Selected> for Record<'a> {
+    fn from(selected: &'a Selected) -> Self {
+        let message = &selected.message;
+        Record {
+            platform: message.platform,
+            video_id: &message.video_id,
+            video_title: &message.video_title,
+            offset: message.offset,
+            user_id: &message.user_id,
+            login: &message.login,
+            display_name: &message.display_name,
+            color: message.color.as_deref(),
+            badges: message
+                .badges
+                .iter()
+                .map(|badge| badge.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+            bits: message.bits,
+            notice: message.notice.as_deref(),
+            body: &message.body,
+            emotes: message
+                .emotes
+                .iter()
+                .map(|emote| emote.name.as_str())
+                .collect::<Vec<_>>()
+                .join(" "),
+            context: selected.is_context,
+        }
+    }
+}
+
+struct Output {
+    format: Format,
+    /// How many messages have been written so far
+    written: usize,
+    csv: Option<csv::Writer<Stdout>>,
+}
+
+lazy_static! {
+    static ref OUTPUT: Mutex<Output> = Mutex::new(Output {
+        format: Format::Text,
+        written: 0,
+        csv: None,
+    });
+}
+
+/// Kept apart from `OUTPUT`, since it is read while `OUTPUT` is locked to print a message
+static SHOW_BADGES: AtomicBool = AtomicBool::new(false);
+
+/// Sets the format that every following message is written in
+pub fn set_format(format: Format) {
+    let mut output = OUTPUT.lock().unwrap();
+    output.format = format;
+    output.csv = match format {
+        Format::Csv => Some(csv::Writer::from_writer(stdout())),
+        _ => None,
+    }
+}
+
+/// Returns the format that messages are currently written in
+pub fn format() -> Format {
+    OUTPUT.lock().unwrap().format
+}
+
+/// Sets whether `Format::Text` lists the badges of each user before their name
+pub fn set_show_badges(show_badges: bool) {
+    SHOW_BADGES.store(show_badges, Ordering::Relaxed)
+}
+
+/// Returns `true` if `Format::Text` lists the badges of each user before their name
+pub fn show_badges() -> bool {
+    SHOW_BADGES.load(Ordering::Relaxed)
+}
+
+/// Returns `true` if stdout is a terminal, and so may be written to with colors
+pub fn use_colors() -> bool {
+    stdout().is_terminal()
+}
+
+/// Writes a single selected message in the current format
+///
+/// In `Format::Text`, context messages are written as `[HH:MM:SS][display_name]- body`, with `--` between unrelated groups
+pub fn write_message(selected: &Selected) -> Result<()> {
+    write_record(&Record::from(selected), || {
+        if selected.after_gap {
+            println!("--")
+        }
+        if selected.is_context {
+            selected.message.print_context()
+        } else {
+            selected.message.print()
+        }
+    })
+}
+
+/// Writes `record` in the current format, or calls `print_text` to write it in `Format::Text`
+///
+/// `record` must serialize to a flat list of fields to be written as CSV
+pub fn write_record<T: Serialize, F: FnOnce()>(record: &T, print_text: F) -> Result<()> {
+    let mut output = OUTPUT.lock().unwrap();
+    match output.format {
+        Format::Text => print_text(),
+        Format::Json => {
+            //the array is closed by `finish()`
+            let separator = if output.written == 0 { "[" } else { "," };
+            let json = serde_json::to_string(record)?;
+            print!("{}\n{}", separator, json)
+        }
+        Format::Ndjson => println!("{}", serde_json::to_string(record)?),
+        Format::Csv => {
+            if let Some(writer) = output.csv.as_mut() {
+                writer.serialize(record)?;
+                writer.flush()?
+            }
+        }
+    }
+    output.written += 1;
+    Ok(())
+}
+
+/// Writes information about the video whose messages come next, such as its title
+///
+/// This goes to stdout for `Format::Text` and to stderr for every other format, so as not to break them
+pub fn write_info<S: Display>(info: S) {
+    match format() {
+        Format::Text => println!("{}", info),
+        _ => eprintln!("{}", info),
+    }
+}
+
+/// Writes whatever the current format needs after the last message, such as the end of the JSON array
+pub fn finish() {
+    let output = OUTPUT.lock().unwrap();
+    if output.format == Format::Json {
+        if output.written == 0 {
+            println!("[]")
+        } else {
+            println!("\n]")
+        }
+    }
+    let _ = stdout().flush();
+    let _ = stderr().flush();
+}
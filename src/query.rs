@@ -0,0 +1,297 @@
+//! What to look for in the chat of a video: which messages match, and which part of the video to read
+use crate::{
+    archive::{self, Comments},
+    chat::ChatMessage,
+    error::Result,
+    expression::Expression,
+    source::VideoSource,
+};
+use regex::Regex;
+use std::collections::VecDeque;
+
+/// A search over the chat of one or more videos
+#[derive(Clone, Debug)]
+pub struct Query {
+    /// Messages whose `body` matches are printed
+    pub filter: Regex,
+    /// Messages must also satisfy this expression, when there is one
+    pub expression: Option<Expression>,
+    /// Seconds into the video from which chat is read
+    pub from: f64,
+    /// Seconds into the video at which chat stops being read, or `None` to read until the end
+    pub to: Option<f64>,
+    /// Only match messages made of nothing but emotes
+    pub emote_only: bool,
+    /// Select the messages that do not match, instead of those that do
+    pub invert: bool,
+    /// Stop reading the chat of a video after this many matches
+    pub max_count: Option<usize>,
+    /// How many messages before each match are selected along with it
+    pub before_context: usize,
+    /// How many messages after each match are selected along with it
+    pub after_context: usize,
+}
+
+/// A message chosen by `Query::select()`
+#[derive(Clone, Debug)]
+pub struct Selected {
+    pub message: ChatMessage,
+    /// `true` if the message was only selected because it came shortly before or after a match
+    pub is_context: bool,
+    /// `true` if messages were skipped between the previous selected message and this one, while context is being selected
+    pub after_gap: bool,
+}
+
+impl Query {
+    /// Creates a new `Query` for every message matching `filter`, over the whole video
+    pub fn new(filter: Regex) -> Self {
+        Query {
+            filter,
+            expression: None,
+            from: 0.0,
+            to: None,
+            emote_only: false,
+            invert: false,
+            max_count: None,
+            before_context: 0,
+            after_context: 0,
+        }
+    }
+
+    /// Limits the query to the messages sent between `from` and `to` seconds into the video
+    pub fn between(mut self, from: f64, to: Option<f64>) -> Self {
+        self.from = from;
+        self.to = to;
+        self
+    }
+
+    /// Limits the query to the messages that satisfy `expression`
+    pub fn matching(mut self, expression: Expression) -> Self {
+        self.expression = Some(expression);
+        self
+    }
+
+    /// Returns `true` if `message` should be printed
+    pub fn is_match(&self, message: &ChatMessage) -> bool {
+        let is_match = self.filter.is_match(&message.body)
+            && (!self.emote_only || message.is_emote_only())
+            && match &self.expression {
+                Some(expression) => expression.matches(message),
+                None => true,
+            };
+        is_match != self.invert
+    }
+
+    /// Returns the messages of `messages` that match, along with the context around them
+    ///
+    /// Nothing more is read from `messages` once `max_count` matches, and the context after the last one, have been selected
+    pub fn select<I: IntoIterator<Item = Result<ChatMessage>>>(
+        &self,
+        messages: I,
+    ) -> Select<'_, I::IntoIter> {
+        Select {
+            query: self,
+            messages: messages.into_iter(),
+            before: VecDeque::new(),
+            selected: VecDeque::new(),
+            after_left: 0,
+            matches: 0,
+            index: 0,
+            last_selected: None,
+            is_finished: false,
+        }
+    }
+
+    /// Returns every message of `video` sent within the range of the query, whether or not it matches
+    ///
+    /// The platform is asked to start at `from`, and nothing more is requested once a message sent at or after `to` is read
+    ///
+    /// When an `archive::Archive` is set, the chat is read from it if it is stored there, and stored there otherwise
+    pub fn read<V: VideoSource>(&self, video: &V) -> impl Iterator<Item = Result<ChatMessage>> {
+        let (from, to) = (self.from, self.to);
+        let comments = match archive::archive() {
+            Some(archive) => archive.comments(video, from),
+            None => Comments::Live(video.comments_from(from)),
+        };
+        comments
+            .skip_while(move |message| matches!(message, Ok(message) if message.offset < from))
+            .take_while(move |message| match (message, to) {
+                (Ok(message), Some(to)) => message.offset < to,
+                _ => true,
+            })
+    }
+}
+
+impl From<Regex> for Query {
+    fn from(filter: Regex) -> Self {
+        Query::new(filter)
+    }
+}
+
+/// The iterator returned by `Query::select()`
+///
+/// The iterator is finished after the first `Err` it returns
+pub struct Select<'a, I> {
+    query: &'a Query,
+    messages: I,
+    /// The last messages that were not selected, with their index, in case a match follows them
+    before: VecDeque<(usize, ChatMessage)>,
+    selected: VecDeque<Selected>,
+    /// How many more messages are selected as the context after the last match
+    after_left: usize,
+    matches: usize,
+    index: usize,
+    last_selected: Option<usize>,
+    is_finished: bool,
+}
+
+impl<'a, I> Select<'a, I> {
+    fn push(&mut self, index: usize, message: ChatMessage, is_context: bool) {
+        let has_context = self.query.before_context > 0 || self.query.after_context > 0;
+        let after_gap = has_context && self.last_selected.is_some_and(|last| index > last + 1);
+        self.last_selected = Some(index);
+        self.selected.push_back(Selected {
+            message,
+            is_context,
+            after_gap,
+        })
+    }
+
+    fn is_at_max_count(&self) -> bool {
+        self.query.max_count.is_some_and(|max| self.matches >= max)
+    }
+}
+
+impl<'a, I: Iterator<Item = Result<ChatMessage>>> Iterator for Select<'a, I> {
+    type Item = Result<Selected>;
+
+    fn next(&mut self) -> Option<Result<Selected>> {
+        loop {
+            if let Some(selected) = self.selected.pop_front() {
+                return Some(Ok(selected));
+            }
+            if self.is_finished || (self.is_at_max_count() && self.after_left == 0) {
+                return None;
+            }
+            let message = match self.messages.next() {
+                Some(Ok(message)) => message,
+                Some(Err(e)) => {
+                    self.is_finished = true;
+                    return Some(Err(e));
+                }
+                None => {
+                    self.is_finished = true;
+                    continue;
+                }
+            };
+            let index = self.index;
+            self.index += 1;
+            if !self.is_at_max_count() && self.query.is_match(&message) {
+                self.matches += 1;
+                for (index, message) in std::mem::take(&mut self.before) {
+                    self.push(index, message, true)
+                }
+                self.push(index, message, false);
+                self.after_left = self.query.after_context;
+            } else if self.after_left > 0 {
+                self.after_left -= 1;
+                self.push(index, message, true)
+            } else if self.query.before_context > 0 {
+                self.before.push_back((index, message));
+                if self.before.len() > self.query.before_context {
+                    self.before.pop_front();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chat::tests::message, error::Error};
+
+    /// Returns the chat `bodies`, one message every second
+    fn chat(bodies: &[&str]) -> Vec<Result<ChatMessage>> {
+        (0..)
+            .zip(bodies)
+            .map(|(offset, body)| Ok(message(f64::from(offset), "alice", body)))
+            .collect()
+    }
+
+    /// Returns the body of every selected message, marked `-` when it is context and `|` when it follows a gap
+    fn selected<I: IntoIterator<Item = Result<ChatMessage>>>(
+        query: &Query,
+        messages: I,
+    ) -> Vec<String> {
+        query
+            .select(messages)
+            .map(|selected| {
+                let selected = selected.unwrap();
+                format!(
+                    "{}{}{}",
+                    if selected.after_gap { "|" } else { "" },
+                    if selected.is_context { "-" } else { "" },
+                    selected.message.body
+                )
+            })
+            .collect()
+    }
+
+    fn query(filter: &str) -> Query {
+        Query::new(Regex::new(filter).unwrap())
+    }
+
+    #[test]
+    fn context_is_selected_around_matches() {
+        let mut query = query("x");
+        query.before_context = 1;
+        query.after_context = 1;
+        let messages = chat(&["a", "b", "x1", "c", "d", "e", "x2", "x3", "f", "g"]);
+        assert_eq!(
+            selected(&query, messages),
+            vec!["-b", "x1", "-c", "|-e", "x2", "x3", "-f"]
+        );
+    }
+
+    #[test]
+    fn context_of_matches_close_together_is_selected_once() {
+        let mut query = query("x");
+        query.before_context = 2;
+        let messages = chat(&["a", "x1", "b", "x2"]);
+        assert_eq!(selected(&query, messages), vec!["-a", "x1", "-b", "x2"]);
+    }
+
+    #[test]
+    fn reading_stops_after_max_count() {
+        let mut query = query("x");
+        query.max_count = Some(2);
+        query.after_context = 1;
+        let mut messages = chat(&["x1", "a", "x2", "x3", "b"]).into_iter();
+        assert_eq!(
+            selected(&query, messages.by_ref()),
+            vec!["x1", "-a", "x2", "-x3"]
+        );
+        //nothing past the context of the last match is read
+        assert_eq!(messages.next().unwrap().unwrap().body, "b");
+    }
+
+    #[test]
+    fn invert_selects_what_does_not_match() {
+        let mut query = query("x");
+        query.invert = true;
+        assert_eq!(selected(&query, chat(&["a", "x1", "b"])), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn errors_end_the_selection() {
+        let mut messages = chat(&["x1", "x2"]);
+        messages.insert(1, Err(Error::Parse("a broken message".to_owned())));
+        messages.push(Ok(message(3.0, "alice", "x3")));
+        let query = query("x");
+        let mut select = query.select(messages);
+        assert_eq!(select.next().unwrap().unwrap().message.body, "x1");
+        assert!(select.next().unwrap().is_err());
+        assert!(select.next().is_none());
+    }
+}
@@ -0,0 +1,87 @@
+use crate::retry_once_then_skip;
+use chat_reader::{
+    highlights::{video_highlights, write_highlights, Highlight, HighlightOptions},
+    output,
+    query::Query,
+    source::read_videos,
+    stats::{video_stats, write_report, ChatStats},
+    Result, VideoSource,
+};
+
+/// Prints the `--stats` of the chat of a single video that matches `query`
+pub(crate) fn stats<V: VideoSource>(
+    video: &V,
+    query: &Query,
+    emote_window: f64,
+    top: usize,
+) -> Result<()> {
+    let stats = video_stats(video, query, emote_window)?;
+    write_report(&stats.report(format!("{} v{}", video.title(), video.id()), top))
+}
+
+/// Prints the `--stats` of every video in `videos`, followed by the totals over all of them
+pub(crate) fn channel_stats<V: VideoSource>(
+    name: &str,
+    videos: Vec<V>,
+    query: &Query,
+    emote_window: f64,
+    top: usize,
+    concurrency: usize,
+) -> Result<()> {
+    let mut total = ChatStats::new();
+    let mut read = 0;
+    let query = query.to_owned();
+    read_videos(
+        videos,
+        concurrency,
+        move |video| video_stats(video, &query, emote_window),
+        retry_once_then_skip(),
+        |video, stats| {
+            total.merge(&stats);
+            read += 1;
+            write_report(&stats.report(format!("{} v{}", video.title(), video.id()), top))
+        },
+    )?;
+    write_report(&total.report(format!("{} ({} videos)", name, read), top))
+}
+
+/// Prints the `--highlights` of the chat of a single video that matches `query`
+pub(crate) fn highlights<V: VideoSource>(
+    video: &V,
+    query: &Query,
+    options: &HighlightOptions,
+    top: usize,
+) -> Result<()> {
+    let highlights = video_highlights(video, query, options, top)?;
+    print_highlights(video, &highlights)
+}
+
+/// Prints the `--highlights` of every video in `videos`, in the same order as `videos`
+pub(crate) fn channel_highlights<V: VideoSource>(
+    videos: Vec<V>,
+    query: &Query,
+    options: &HighlightOptions,
+    top: usize,
+    concurrency: usize,
+) -> Result<()> {
+    let (query, options) = (query.to_owned(), options.to_owned());
+    read_videos(
+        videos,
+        concurrency,
+        move |video| video_highlights(video, &query, &options, top),
+        retry_once_then_skip(),
+        |video, highlights| print_highlights(video, &highlights),
+    )
+}
+
+fn print_highlights<V: VideoSource>(video: &V, highlights: &[Highlight]) -> Result<()> {
+    if highlights.is_empty() {
+        output::write_info(format!(
+            "\n{} v{}\nNo highlights found",
+            video.title(),
+            video.id()
+        ));
+        return Ok(());
+    }
+    write_highlights(highlights)
+}
@@ -0,0 +1,587 @@
+//! Reads chat that is already on disk, so that it can be searched, reported on and exported without any network calls
+//!
+//! The JSON and NDJSON written by `--format`, archive directories, JSON saved by Twitch chat downloaders,
+//! GQL responses of `VideoCommentsByOffsetOrCursor` and the XML of AfreecaTV's `ChatLoadSplit.php` are all read,
+//! and the messages are grouped into one `SavedVideo` per video they belong to
+use crate::{
+    afreecatv_video::{parse_chat_xml, video_link},
+    archive::{Archive, StoredComments},
+    chat::{Badge, ChatMessage, Emote, Platform},
+    error::{Error, Result},
+    source::{Resumable, VideoSource},
+    tools::error,
+    twitch_vod::{parse_comment, vod_link},
+};
+use serde::{de, Deserialize, Deserializer};
+use serde_json::Value;
+use std::{
+    fs::{self, File},
+    io::{stdin, BufRead, BufReader, Read},
+    path::Path,
+    sync::Arc,
+};
+
+/// A video whose chat was read from a file instead of from its platform
+#[derive(Clone, Debug)]
+pub struct SavedVideo {
+    pub platform: Platform,
+    pub id: String,
+    pub title: String,
+    source: Source,
+}
+
+/// Where the chat of a `SavedVideo` comes from
+#[derive(Clone, Debug)]
+enum Source {
+    /// Messages that were parsed when the file was read
+    Messages(Arc<Vec<ChatMessage>>),
+    /// An archive holding the chat of the video, which is only read when the comments are
+    Archive(Archive),
+}
+
+impl SavedVideo {
+    /// Creates a `SavedVideo` from messages that all belong to the same video
+    pub fn new(platform: Platform, id: String, title: String, messages: Vec<ChatMessage>) -> Self {
+        SavedVideo {
+            platform,
+            id,
+            title,
+            source: Source::Messages(Arc::new(messages)),
+        }
+    }
+
+    /// Creates a `SavedVideo` whose chat is read lazily from `archive`, where it must be stored
+    pub fn archived(archive: Archive, platform: Platform, id: String) -> Result<Self> {
+        //the title is only stored with each message, so it is taken from the first one
+        let title = match archive.read(platform, &id)?.next() {
+            Some(message) => message?.video_title,
+            None => String::new(),
+        };
+        Ok(SavedVideo {
+            platform,
+            id,
+            title,
+            source: Source::Archive(archive),
+        })
+    }
+}
+
+/// The iterator returned by `SavedVideo::comments()`
+pub enum SavedComments {
+    Messages {
+        messages: Arc<Vec<ChatMessage>>,
+        index: usize,
+    },
+    Stored(StoredComments),
+    /// The archive could not be read, which is returned once
+    Failed(Option<Error>),
+}
+
+impl Iterator for SavedComments {
+    type Item = Result<ChatMessage>;
+
+    fn next(&mut self) -> Option<Result<ChatMessage>> {
+        match self {
+            SavedComments::Messages { messages, index } => {
+                let message = messages.get(*index)?.to_owned();
+                *index += 1;
+                Some(Ok(message))
+            }
+            SavedComments::Stored(stored) => stored.next(),
+            SavedComments::Failed(e) => e.take().map(Err),
+        }
+    }
+}
+
+impl Resumable for SavedComments {
+    /// The number of saved messages read, which is only known for messages parsed from a file
+    type Cursor = usize;
+
+    fn cursor(&self) -> Option<usize> {
+        match self {
+            SavedComments::Messages { index, .. } => Some(*index),
+            _ => None,
+        }
+    }
+}
+
+impl VideoSource for SavedVideo {
+    type Comments = SavedComments;
+
+    fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    fn id(&self) -> String {
+        self.id.to_owned()
+    }
+
+    fn title(&self) -> String {
+        self.title.to_owned()
+    }
+
+    fn comments_from(&self, offset: f64) -> SavedComments {
+        match &self.source {
+            Source::Messages(messages) => SavedComments::Messages {
+                index: messages.partition_point(|message| message.offset < offset),
+                messages: messages.to_owned(),
+            },
+            Source::Archive(archive) => match archive.read(self.platform, &self.id) {
+                Ok(stored) => SavedComments::Stored(stored),
+                Err(e) => SavedComments::Failed(Some(e)),
+            },
+        }
+    }
+
+    fn comments_at(&self, cursor: usize) -> SavedComments {
+        match &self.source {
+            Source::Messages(messages) => SavedComments::Messages {
+                messages: messages.to_owned(),
+                index: cursor,
+            },
+            Source::Archive(_) => self.comments(),
+        }
+    }
+
+    /// Saved chat has no playlist, so this is a link to watch the video instead
+    fn playback_url(&self) -> Result<String> {
+        Ok(self.link_at(0.0))
+    }
+
+    fn link_at(&self, offset: f64) -> String {
+        match self.platform {
+            Platform::Twitch => vod_link(&self.id, offset),
+            Platform::AfreecaTV => video_link(&self.id, offset),
+        }
+    }
+}
+
+/// Reads the chat saved in every file of `paths`, returning one `SavedVideo` per video in the order they were first seen
+///
+/// `-` reads from stdin, and directories are read recursively, skipping the files in them that do not hold chat.
+/// Files of an archive, `<platform>/<id>.ndjson`, are only read once their comments are
+pub fn read_paths<P: AsRef<Path>>(paths: &[P]) -> Result<Vec<SavedVideo>> {
+    let mut videos = Vec::new();
+    let mut messages = Vec::new();
+    for path in paths {
+        let path = path.as_ref();
+        if path == Path::new("-") {
+            let mut text = String::new();
+            stdin().read_to_string(&mut text)?;
+            messages.extend(parse_saved(&text, "stdin")?);
+        } else {
+            read_path(path, true, &mut videos, &mut messages)?
+        }
+    }
+    videos.extend(group(messages));
+    Ok(videos)
+}
+
+/// Reads `path`, pushing the videos of archive files to `videos` and every other message to `messages`
+///
+/// Files found in a directory that turn out not to hold chat, such as a stats report or a `--record`ing, are reported and skipped;
+/// only a file that was `named` fails the read
+fn read_path(
+    path: &Path,
+    named: bool,
+    videos: &mut Vec<SavedVideo>,
+    messages: &mut Vec<ChatMessage>,
+) -> Result<()> {
+    if path.is_dir() {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(path)? {
+            entries.push(entry?.path())
+        }
+        entries.sort();
+        for entry in entries {
+            let is_chat = entry.is_dir()
+                || entry.extension().is_some_and(|extension| {
+                    ["json", "ndjson", "jsonl", "xml"]
+                        .iter()
+                        .any(|e| extension == *e)
+                });
+            if is_chat {
+                read_path(&entry, false, videos, messages)?
+            }
+        }
+        return Ok(());
+    }
+    if !path.exists() {
+        return Err(Error::NotFound(path.display().to_string()));
+    }
+    if let Some((archive, platform, id)) = archived(path) {
+        videos.push(SavedVideo::archived(archive, platform, id)?);
+        return Ok(());
+    }
+    let name = match path.file_stem() {
+        Some(stem) => stem.to_string_lossy().into_owned(),
+        None => path.display().to_string(),
+    };
+    let mut text = String::new();
+    BufReader::new(File::open(path)?).read_to_string(&mut text)?;
+    match parse_saved(&text, &name) {
+        Ok(parsed) => messages.extend(parsed),
+        Err(e) if named => return Err(Error::Parse(format!("{}: {}", path.display(), e))),
+        Err(e) => error(format!(
+            "Skipped {}, which is not saved chat: {}\n",
+            path.display(),
+            e
+        )),
+    }
+    Ok(())
+}
+
+/// Returns the archive, platform and video ID of `path` if it is the chat of a video in an archive
+fn archived(path: &Path) -> Option<(Archive, Platform, String)> {
+    if path.extension()? != "ndjson" {
+        return None;
+    }
+    let platform_dir = path.parent()?;
+    let platform = match platform_dir.file_name()?.to_str()? {
+        "twitch" => Platform::Twitch,
+        "afreecatv" => Platform::AfreecaTV,
+        _ => return None,
+    };
+    let id = path.file_stem()?.to_string_lossy().into_owned();
+    let archive = Archive::new(platform_dir.parent()?);
+    //only files of stored `ChatMessage`s are read lazily, which is checked on their first line
+    let mut first = BufReader::new(File::open(path).ok()?).lines();
+    match first.next() {
+        Some(Ok(line)) if serde_json::from_str::<ChatMessage>(&line).is_ok() => {
+            Some((archive, platform, id))
+        }
+        _ => None,
+    }
+}
+
+/// Splits `messages` into one `SavedVideo` per video, in the order each video was first seen
+fn group(messages: Vec<ChatMessage>) -> Vec<SavedVideo> {
+    let mut videos: Vec<(Platform, String, String, Vec<ChatMessage>)> = Vec::new();
+    for message in messages {
+        let video = videos
+            .iter_mut()
+            .find(|(platform, id, ..)| *platform == message.platform && *id == message.video_id);
+        match video {
+            Some((_, _, title, messages)) => {
+                if title.is_empty() {
+                    *title = message.video_title.to_owned()
+                }
+                messages.push(message)
+            }
+            None => videos.push((
+                message.platform,
+                message.video_id.to_owned(),
+                message.video_title.to_owned(),
+                vec![message],
+            )),
+        }
+    }
+    videos
+        .into_iter()
+        .map(|(platform, id, title, mut messages)| {
+            messages.sort_by(|a, b| a.offset.total_cmp(&b.offset));
+            SavedVideo::new(platform, id, title, messages)
+        })
+        .collect()
+}
+
+/// Parses the chat saved in `text`, in any of the formats that can be read
+///
+/// `name` is the video ID of messages that do not say which video they belong to
+pub fn parse_saved(text: &str, name: &str) -> Result<Vec<ChatMessage>> {
+    let text = text.trim_start_matches('\u{feff}').trim();
+    if text.starts_with('<') {
+        return parse_chat_xml(text, name, "", 0);
+    }
+    //a whole document is tried first, since a JSON object may span several lines
+    let values = match serde_json::from_str::<Value>(text) {
+        Ok(Value::Array(values)) => values,
+        Ok(document) => return parse_document(document, name),
+        Err(_) => {
+            let mut values = Vec::new();
+            for line in text.lines().filter(|line| !line.trim().is_empty()) {
+                values.push(serde_json::from_str(line)?)
+            }
+            values
+        }
+    };
+    values
+        .into_iter()
+        .map(|value| parse_value(value, name, ""))
+        .collect()
+}
+
+/// Parses a JSON object that is either a whole saved chat or a single message
+fn parse_document(document: Value, name: &str) -> Result<Vec<ChatMessage>> {
+    //the JSON of Twitch chat downloaders, with the video next to its comments
+    if let Some(Value::Array(comments)) = document.get("comments") {
+        let id = match document.pointer("/video/id") {
+            Some(Value::String(id)) => id.to_owned(),
+            Some(Value::Number(id)) => id.to_string(),
+            _ => name.to_owned(),
+        };
+        let title = document
+            .pointer("/video/title")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        return comments
+            .iter()
+            .map(|comment| parse_value(comment.to_owned(), &id, title))
+            .collect();
+    }
+    //a saved `VideoCommentsByOffsetOrCursor` response
+    if let Some(Value::Array(edges)) = document.pointer("/data/video/comments/edges") {
+        let title = document
+            .pointer("/data/video/title")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        return edges
+            .iter()
+            .filter_map(|edge| edge.get("node"))
+            .map(|node| parse_comment(node.to_owned(), name, title))
+            .collect();
+    }
+    Ok(vec![parse_value(document, name, "")?])
+}
+
+/// Parses a single saved message, either a Twitch comment or a message written by this crate
+fn parse_value(value: Value, id: &str, title: &str) -> Result<ChatMessage> {
+    let is_comment = [
+        "commenter",
+        "contentOffsetSeconds",
+        "content_offset_seconds",
+    ]
+    .iter()
+    .any(|key| value.get(key).is_some());
+    if is_comment {
+        //comments saved by chat downloaders say which VOD they were left on
+        let id = match value.get("content_id") {
+            Some(Value::String(id)) => id.to_owned(),
+            _ => id.to_owned(),
+        };
+        return parse_comment(value, &id, title);
+    }
+    let saved: SavedMessage = serde_json::from_value(value.to_owned())?;
+    Ok(saved.into_message(value, id, title))
+}
+
+/// A message as written by `--format json` or `ndjson`, or as stored in an archive
+///
+/// Badges and emotes are read both as the lists of a `ChatMessage` and as the strings of `--format`
+#[derive(Deserialize)]
+struct SavedMessage {
+    platform: Option<Platform>,
+    #[serde(default)]
+    video_id: Option<String>,
+    #[serde(default)]
+    video_title: String,
+    #[serde(deserialize_with = "offset")]
+    offset: f64,
+    #[serde(default)]
+    user_id: String,
+    #[serde(default)]
+    login: String,
+    #[serde(default)]
+    display_name: String,
+    #[serde(default)]
+    color: Option<String>,
+    #[serde(default)]
+    badges: Value,
+    #[serde(default)]
+    emotes: Value,
+    #[serde(default)]
+    bits: u32,
+    #[serde(default)]
+    notice: Option<String>,
+    body: String,
+    #[serde(default)]
+    raw: Option<Value>,
+}
+
+/// Reads the offset of a `SavedMessage`, which must be a finite number of seconds that is not negative
+fn offset<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<f64, D::Error> {
+    let offset = f64::deserialize(deserializer)?;
+    if offset.is_finite() && offset >= 0.0 {
+        Ok(offset)
+    } else {
+        Err(de::Error::custom(format!(
+            "{} is not a number of seconds into a video",
+            offset
+        )))
+    }
+}
+
+impl SavedMessage {
+    fn into_message(self, value: Value, id: &str, title: &str) -> ChatMessage {
+        let badges = match self.badges {
+            Value::String(badges) => badges
+                .split_whitespace()
+                .map(|badge| {
+                    let (name, version) = badge.split_once('/').unwrap_or((badge, ""));
+                    Badge {
+                        name: name.to_owned(),
+                        version: version.to_owned(),
+                    }
+                })
+                .collect(),
+            badges => serde_json::from_value(badges).unwrap_or_default(),
+        };
+        let emotes = match self.emotes {
+            //only the names are written, so each is found in the body after the one before it
+            Value::String(names) => {
+                let mut emotes = Vec::new();
+                let mut searched = 0;
+                for name in names.split_whitespace() {
+                    if let Some(start) = self.body[searched..].find(name) {
+                        emotes.push(Emote {
+                            id: String::new(),
+                            name: name.to_owned(),
+                            start: searched + start,
+                        });
+                        searched += start + name.len()
+                    }
+                }
+                emotes
+            }
+            emotes => serde_json::from_value(emotes).unwrap_or_default(),
+        };
+        let video_title = if self.video_title.is_empty() {
+            title.to_owned()
+        } else {
+            self.video_title
+        };
+        ChatMessage {
+            platform: self.platform.unwrap_or(Platform::Twitch),
+            video_id: self.video_id.unwrap_or_else(|| id.to_owned()),
+            video_title,
+            offset: self.offset,
+            user_id: self.user_id,
+            login: self.login,
+            display_name: self.display_name,
+            color: self.color,
+            badges,
+            emotes,
+            bits: self.bits,
+            notice: self.notice,
+            body: self.body,
+            raw: self.raw.unwrap_or(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_of_format_is_read_back() {
+        let line = r##"{"platform":"twitch","video_id":"7","video_title":"Test VOD","offset":12.5,"user_id":"1","login":"alice","display_name":"Alice","color":"#FF0000","badges":"subscriber/12 moderator/1","bits":100,"notice":"resub","body":"hi Kappa Kappa","emotes":"Kappa Kappa","context":false}"##;
+        let ndjson = format!("{}\n\n{}\n", line, line.replace("12.5", "20"));
+        for text in [
+            ndjson.to_owned(),
+            format!("[{}]", ndjson.trim().replace("\n\n", ",")),
+        ] {
+            let messages = parse_saved(&text, "name").unwrap();
+            assert_eq!(messages.len(), 2);
+            let message = &messages[0];
+            assert_eq!((message.video_id.as_str(), message.offset), ("7", 12.5));
+            assert_eq!(message.badges[1].to_string(), "moderator/1");
+            assert_eq!(message.bits, 100);
+            assert_eq!(message.notice.as_deref(), Some("resub"));
+            let starts: Vec<usize> = message.emotes.iter().map(|emote| emote.start).collect();
+            assert_eq!(starts, vec![3, 9]);
+        }
+    }
+
+    #[test]
+    fn chat_downloader_json_is_read() {
+        let document = r##"{
+            "video": {"id": 42, "title": "Saved VOD"},
+            "comments": [{
+                "_id": "a",
+                "content_id": "42",
+                "content_offset_seconds": 61,
+                "commenter": {"_id": "1", "name": "alice", "display_name": "Alice"},
+                "message": {
+                    "body": "hello Kappa",
+                    "fragments": [{"text": "hello "}, {"text": "Kappa", "emoticon": {"emoticon_id": "25"}}],
+                    "user_badges": [{"_id": "moderator", "version": "1"}],
+                    "user_color": "#00FF00"
+                }
+            }]
+        }"##;
+        let messages = parse_saved(document, "name").unwrap();
+        assert_eq!(messages.len(), 1);
+        let message = &messages[0];
+        assert_eq!(message.video_id, "42");
+        assert_eq!(message.video_title, "Saved VOD");
+        assert_eq!(message.offset, 61.0);
+        assert_eq!(message.display_name, "Alice");
+        assert_eq!(message.badges[0].name, "moderator");
+        assert_eq!(message.emotes[0].id, "25");
+    }
+
+    #[test]
+    fn gql_responses_are_read() {
+        let response = r#"{"data": {"video": {"title": "Test VOD", "comments": {"edges": [
+            {"cursor": "1", "node": {"commenter": null, "contentOffsetSeconds": 5, "message": {"fragments": [{"text": "hi", "emote": null}]}}}
+        ]}}}}"#;
+        let messages = parse_saved(response, "9").unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].video_id, "9");
+        assert_eq!(messages[0].video_title, "Test VOD");
+        assert_eq!(messages[0].body, "hi");
+    }
+
+    #[test]
+    fn afreecatv_xml_is_read() {
+        let xml = "\u{feff}<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<root><chat><u>user1</u><v>0</v><s>0</s><n>Nick</n><m>안녕 hi</m><f>0</f><t>12.5</t></chat></root>";
+        let messages = parse_saved(xml, "123").unwrap();
+        assert_eq!(messages.len(), 1);
+        let message = &messages[0];
+        assert_eq!(message.platform, Platform::AfreecaTV);
+        assert_eq!(message.video_id, "123");
+        assert_eq!(message.user_id, "user1");
+        assert_eq!(message.display_name, "Nick");
+        assert_eq!(message.body, "안녕 hi");
+        assert_eq!(message.offset, 12.5);
+    }
+
+    #[test]
+    fn anything_else_is_rejected() {
+        for text in ["{\"messages\": 3}", "not chat", "<xml"] {
+            assert!(parse_saved(text, "name").is_err(), "{:?}", text)
+        }
+        //an empty file is chat without any messages
+        assert!(parse_saved(" \n", "name").unwrap().is_empty());
+    }
+
+    #[test]
+    fn offsets_must_be_seconds_into_the_video() {
+        for offset in ["-1", "1e400", "\"12\""] {
+            let line = format!(r#"{{"offset":{},"body":"hi"}}"#, offset);
+            assert!(parse_saved(&line, "name").is_err(), "{}", offset)
+        }
+        let far = parse_saved(r#"{"offset":1e15,"body":"hi"}"#, "name").unwrap();
+        assert_eq!(far[0].offset, 1e15);
+    }
+
+    #[test]
+    fn files_that_are_not_chat_are_only_skipped_in_directories() {
+        let dir = std::env::temp_dir().join(format!("chat_reader_saved_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let chat = r#"{"video_id":"7","offset":1,"display_name":"Alice","body":"hi"}"#;
+        fs::write(dir.join("chat.ndjson"), chat).unwrap();
+        fs::write(dir.join("stats.json"), "{\"messages\": 3}").unwrap();
+        let videos = read_paths(&[&dir]).unwrap();
+        assert_eq!(videos.len(), 1);
+        assert_eq!(videos[0].id(), "7");
+        assert!(read_paths(&[dir.join("stats.json")]).is_err());
+        assert!(matches!(
+            read_paths(&[dir.join("missing.json")]),
+            Err(Error::NotFound(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
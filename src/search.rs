@@ -0,0 +1,65 @@
+use chat_reader::{
+    archive::{self, Archive},
+    index::{self, parse_search, ChatIndex},
+    output,
+    query::Selected,
+    Error, Platform, Result, VideoSource,
+};
+
+/// Runs the `search` command, printing every message of the index of `channel` that matches `search`
+pub(crate) fn run(
+    archive: &Archive,
+    platform: Platform,
+    channel: &str,
+    search: &str,
+) -> Result<()> {
+    let terms = parse_search(search)?;
+    let index = ChatIndex::open(archive, platform, channel);
+    let videos = index.videos()?;
+    if videos.is_empty() {
+        return Err(Error::NotFound(format!(
+            "the chat index of {} in {}",
+            channel,
+            archive.dir().display()
+        )));
+    }
+    let hits = index.search(&terms)?;
+    if hits.is_empty() {
+        output::write_info(format!(
+            "No messages found in {} videos of {}",
+            videos.len(),
+            channel
+        ));
+        return Ok(());
+    }
+    let messages = index::read_hits(archive, platform, &hits)?;
+    let mut video = None;
+    for (hit, message) in hits.iter().zip(messages) {
+        if video != Some(&hit.video.id) {
+            output::write_info(format!("\n{} v{}", hit.video.title, hit.video.id));
+            video = Some(&hit.video.id)
+        }
+        output::write_message(&Selected {
+            message,
+            is_context: false,
+            after_gap: false,
+        })?
+    }
+    Ok(())
+}
+
+/// Indexes the chat of every video of `videos` that was archived since the index of `channel` was last synced
+///
+/// Does nothing when chat is not being archived
+pub(crate) fn sync_index<V: VideoSource>(channel: &str, videos: &[V]) -> Result<()> {
+    if let Some(archive) = archive::archive().filter(|archive| archive.stores_chat()) {
+        let added = index::sync(&archive, channel, videos)?;
+        if added > 0 {
+            output::write_info(format!(
+                "\nIndexed the chat of {} more videos of {}",
+                added, channel
+            ))
+        }
+    }
+    Ok(())
+}
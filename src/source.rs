@@ -0,0 +1,224 @@
+use crate::{
+    chat::{self, ChatMessage, Platform},
+    error::{Error, Result},
+    output,
+    query::Query,
+    tools::error,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::VecDeque,
+    sync::mpsc::{channel, Receiver, Sender},
+    thread::{spawn, JoinHandle},
+};
+
+/// Chat that can tell how far it has been read, so that reading can be resumed there after being interrupted
+pub trait Resumable: Iterator<Item = Result<ChatMessage>> {
+    /// Where reading resumes, which is kept on disk along with the messages read before it
+    type Cursor: Serialize + DeserializeOwned;
+
+    /// Returns the cursor from which reading resumes right after the last message returned, if it can be resumed
+    fn cursor(&self) -> Option<Self::Cursor>;
+}
+
+/// A single video, on any platform, that has chat which can be read
+pub trait VideoSource: Clone + Send + 'static {
+    /// The iterator returned by `comments()`
+    type Comments: Resumable;
+
+    fn platform(&self) -> Platform;
+
+    /// Returns the platform specific ID of the video, such as `TwitchVOD.id` or `AfreecaVideo.title_no`
+    fn id(&self) -> String;
+
+    fn title(&self) -> String;
+
+    /// Returns an iterator over every chat message left on the video, in the order they were sent
+    ///
+    /// The iterator is finished after the first `Err` it returns
+    fn comments(&self) -> Self::Comments {
+        self.comments_from(0.0)
+    }
+
+    /// Identical to `comments()`, except that the platform is asked to start at `offset` seconds into the video
+    ///
+    /// Platforms can only seek so precisely, so a few messages sent before `offset` may still be returned;
+    /// `Query::read()` drops them
+    fn comments_from(&self, offset: f64) -> Self::Comments;
+
+    /// Identical to `comments()`, except that reading resumes at `cursor`, as returned by `Resumable::cursor()`
+    fn comments_at(&self, cursor: <Self::Comments as Resumable>::Cursor) -> Self::Comments;
+
+    /// Returns the best link available to play the video back, such as a direct M3U8 playlist
+    fn playback_url(&self) -> Result<String>;
+
+    /// Returns a link to the video on its platform's website that starts playing at `offset` seconds
+    fn link_at(&self, offset: f64) -> String;
+
+    /// Prints the chat to console from an individual video
+    ///
+    /// See `chat::print_chat()` for how the query and `rx: Receiver<()>` are used
+    fn print_chat(&self, query: &Query, rx: Receiver<()>) -> Result<()> {
+        chat::print_chat(query.read(self), query, rx)
+    }
+
+    /// Identical function to `print_chat()` except that no Receiver<()> is required.
+    ///
+    /// Comments will be printed as soon as they are parsed and will not remain in a queue
+    fn print_chat_blocking(&self, query: &Query) -> Result<()> {
+        let (tx, rx) = channel();
+        tx.send(()).unwrap(); //print immediately
+        self.print_chat(query, rx)
+    }
+}
+
+/// A channel, on any platform, that has a list of videos
+pub trait ChannelSource {
+    type Video: VideoSource;
+
+    /// Returns the videos that are associated with the channel
+    fn videos(&self) -> Result<Vec<Self::Video>>;
+}
+
+/// What to do when the chat of one of the videos passed to `print_videos()` could not be read
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorAction {
+    /// Move on to the next video
+    Skip,
+    /// Read the chat of the video again, continuing after what was already printed
+    Retry,
+    /// Stop reading any more videos and return the error
+    Abort,
+}
+
+/// A queued video reader: `(video, print signal, chat thread, playback URL thread)`
+///
+/// The chat thread returns how many messages it printed along with its result
+type VideoReader<V> = (
+    V,
+    Sender<()>,
+    JoinHandle<(Result<()>, usize)>,
+    JoinHandle<Result<String>>,
+);
+
+/// Starts reading the chat and the playback URL of `video`, each on its own thread
+fn start_reading<V: VideoSource>(video: V, query: &Query) -> VideoReader<V> {
+    //The threads must own all the parameters
+    let (tx, rx) = channel();
+    let video_thread = video.to_owned();
+    let query = query.to_owned();
+    let chat_thread = spawn(move || {
+        let mut printed = 0;
+        let result = chat::print_chat_after(query.read(&video_thread), &query, rx, &mut printed);
+        (result, printed)
+    });
+    let video_thread = video.to_owned();
+    let url_thread = spawn(move || video_thread.playback_url());
+    (video, tx, chat_thread, url_thread)
+}
+
+/// Prints the chat of every video in `videos` that matches `query`, along with the title and playback URL of each video
+///
+/// Up to `concurrency` videos are read at the same time, each on its own thread, but the output is printed in the same order as `videos`
+///
+/// Whenever the chat of a video cannot be read, `on_error` decides what happens next.
+/// A retried video continues after the last message that was printed, counting it towards the query's `max_count`
+pub fn print_videos<V, F>(
+    videos: Vec<V>,
+    query: &Query,
+    concurrency: usize,
+    mut on_error: F,
+) -> Result<()>
+where
+    V: VideoSource,
+    F: FnMut(&V, &Error) -> ErrorAction,
+{
+    let mut videos = videos.into_iter();
+    let mut threads: VecDeque<VideoReader<V>> = VecDeque::new();
+    loop {
+        while threads.len() < concurrency.max(1) {
+            match videos.next() {
+                Some(video) => threads.push_back(start_reading(video, query)),
+                None => break,
+            }
+        }
+        let (video, tx, chat_thread, url_thread) = match threads.pop_front() {
+            Some(reader) => reader,
+            None => break,
+        };
+        output::write_info(format!("\n{} v{}", video.title(), video.id()));
+        match url_thread.join().unwrap() {
+            Ok(url) => output::write_info(url),
+            Err(e) => error(format!("{}\n", e)),
+        }
+        //the chat thread may have already finished if nothing matched the query
+        let _ = tx.send(());
+        let (mut result, mut printed) = chat_thread.join().unwrap();
+        while let Err(e) = result {
+            match on_error(&video, &e) {
+                ErrorAction::Skip => break,
+                ErrorAction::Retry => {
+                    let (tx, rx) = channel();
+                    tx.send(()).unwrap(); //print immediately
+                    result = chat::print_chat_after(query.read(&video), query, rx, &mut printed)
+                }
+                ErrorAction::Abort => return Err(e),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads every video in `videos` with `read`, handing each result to `handle` in the same order as `videos`
+///
+/// Up to `concurrency` videos are read at the same time, each on its own thread.
+/// Whenever `read` fails, `on_error` decides what happens next, as in `print_videos()`;
+/// whatever a failed `read` got through is thrown away, and a retried video is read again from the start
+pub fn read_videos<V, T, R, F, H>(
+    videos: Vec<V>,
+    concurrency: usize,
+    read: R,
+    mut on_error: F,
+    mut handle: H,
+) -> Result<()>
+where
+    V: VideoSource,
+    T: Send + 'static,
+    R: Fn(&V) -> Result<T> + Clone + Send + 'static,
+    F: FnMut(&V, &Error) -> ErrorAction,
+    H: FnMut(&V, T) -> Result<()>,
+{
+    let mut videos = videos.into_iter();
+    let mut threads: VecDeque<(V, JoinHandle<Result<T>>)> = VecDeque::new();
+    loop {
+        while threads.len() < concurrency.max(1) {
+            match videos.next() {
+                Some(video) => {
+                    let video_thread = video.to_owned();
+                    let read = read.to_owned();
+                    threads.push_back((video, spawn(move || read(&video_thread))))
+                }
+                None => break,
+            }
+        }
+        let (video, thread) = match threads.pop_front() {
+            Some(reader) => reader,
+            None => break,
+        };
+        let mut result = thread.join().unwrap();
+        loop {
+            match result {
+                Ok(value) => {
+                    handle(&video, value)?;
+                    break;
+                }
+                Err(e) => match on_error(&video, &e) {
+                    ErrorAction::Skip => break,
+                    ErrorAction::Retry => result = read(&video),
+                    ErrorAction::Abort => return Err(e),
+                },
+            }
+        }
+    }
+    Ok(())
+}
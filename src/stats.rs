@@ -0,0 +1,464 @@
+//! Activity statistics over the chat of one or more videos
+use crate::{
+    chat::ChatMessage, error::Result, output, query::Query, source::VideoSource, tools::format_time,
+};
+use crossterm::{
+    execute,
+    style::{Color, Print, ResetColor, SetForegroundColor},
+    terminal,
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::stdout,
+};
+
+/// How many of a chatter's, or a window's, favorite emotes are listed in a `StatsReport`
+const FAVORITE_EMOTES: usize = 3;
+
+/// Counts of the chat of a video, built up one message at a time with `add()`
+#[derive(Clone, Debug)]
+pub struct ChatStats {
+    pub total: usize,
+    /// Every chatter, by login
+    pub chatters: HashMap<String, Chatter>,
+    /// How many times each emote was used, by name
+    pub emotes: HashMap<String, usize>,
+    /// How many messages were sent in each minute of the video that had any, by minute
+    pub per_minute: BTreeMap<u64, usize>,
+    /// Seconds of chat whose emotes are counted together in `emotes_per_window`
+    pub emote_window: f64,
+    /// How many times each emote was used in each window of `emote_window` seconds that had any, by window
+    pub emotes_per_window: BTreeMap<u64, HashMap<String, usize>>,
+    /// Minutes of chat counted by `merge()`, on top of those in `per_minute`
+    merged_minutes: usize,
+}
+
+/// The counts of a single chatter of `ChatStats`
+#[derive(Clone, Debug, Default)]
+pub struct Chatter {
+    pub display_name: String,
+    pub messages: usize,
+    /// How many times the chatter used each emote, by name
+    pub emotes: HashMap<String, usize>,
+}
+
+/// A summary of `ChatStats`, as it is written by `--format json`
+#[derive(Serialize, Debug)]
+pub struct StatsReport {
+    pub title: String,
+    pub total_messages: usize,
+    pub unique_chatters: usize,
+    pub messages_per_minute: f64,
+    pub top_chatters: Vec<ChatterCount>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub top_emotes: Vec<EmoteCount>,
+    /// Left empty for `merge()`d stats, whose minutes do not line up
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub busiest_minutes: Vec<MinuteCount>,
+    /// How many messages were sent in each minute that had any, by minute
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub per_minute: BTreeMap<u64, usize>,
+    /// The favorite emotes of every window of `ChatStats.emote_window` seconds that had any
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub emote_windows: Vec<WindowEmotes>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ChatterCount {
+    pub name: String,
+    pub messages: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub favorite_emotes: Vec<EmoteCount>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EmoteCount {
+    pub name: String,
+    pub uses: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MinuteCount {
+    pub minute: u64,
+    /// The start of the minute as `HH:MM:SS`
+    pub timestamp: String,
+    pub messages: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WindowEmotes {
+    /// Seconds into the video at which the window starts
+    pub start: f64,
+    /// `start` as `HH:MM:SS`
+    pub timestamp: String,
+    pub favorite_emotes: Vec<EmoteCount>,
+}
+
+impl Default for ChatStats {
+    fn default() -> Self {
+        ChatStats::new()
+    }
+}
+
+impl ChatStats {
+    /// Creates empty stats, counting emotes in windows of 10 minutes
+    pub fn new() -> Self {
+        ChatStats::with_emote_window(600.0)
+    }
+
+    /// Creates empty stats, counting emotes in windows of `emote_window` seconds
+    pub fn with_emote_window(emote_window: f64) -> Self {
+        ChatStats {
+            total: 0,
+            chatters: HashMap::new(),
+            emotes: HashMap::new(),
+            per_minute: BTreeMap::new(),
+            emote_window: emote_window.max(1.0),
+            emotes_per_window: BTreeMap::new(),
+            merged_minutes: 0,
+        }
+    }
+
+    pub fn add(&mut self, message: &ChatMessage) {
+        self.total += 1;
+        let key = if message.login.is_empty() {
+            message.display_name.to_lowercase()
+        } else {
+            message.login.to_owned()
+        };
+        let chatter = self.chatters.entry(key).or_insert_with(|| Chatter {
+            display_name: message.display_name.to_owned(),
+            ..Chatter::default()
+        });
+        chatter.messages += 1;
+        //only the minutes and windows that have messages are kept, however far into the video they are
+        let minute = (message.offset.max(0.0) / 60.0) as u64;
+        *self.per_minute.entry(minute).or_insert(0) += 1;
+        if message.emotes.is_empty() {
+            return;
+        }
+        let window = (message.offset.max(0.0) / self.emote_window) as u64;
+        let window_emotes = self.emotes_per_window.entry(window).or_default();
+        for emote in &message.emotes {
+            *self.emotes.entry(emote.name.to_owned()).or_insert(0) += 1;
+            *chatter.emotes.entry(emote.name.to_owned()).or_insert(0) += 1;
+            *window_emotes.entry(emote.name.to_owned()).or_insert(0) += 1
+        }
+    }
+
+    /// Adds the counts of `other`, from a different video, to these
+    ///
+    /// `per_minute` and `emotes_per_window` are left as they are, since the minutes of different videos do not line up
+    pub fn merge(&mut self, other: &ChatStats) {
+        self.total += other.total;
+        for (login, other_chatter) in &other.chatters {
+            let chatter = self
+                .chatters
+                .entry(login.to_owned())
+                .or_insert_with(|| Chatter {
+                    display_name: other_chatter.display_name.to_owned(),
+                    ..Chatter::default()
+                });
+            chatter.messages += other_chatter.messages;
+            add_counts(&mut chatter.emotes, &other_chatter.emotes)
+        }
+        add_counts(&mut self.emotes, &other.emotes);
+        self.merged_minutes += other.minutes()
+    }
+
+    pub fn unique_chatters(&self) -> usize {
+        self.chatters.len()
+    }
+
+    /// Returns how many minutes of chat were counted, from the first message to the last of each video
+    pub fn minutes(&self) -> usize {
+        let span = match minute_range(&self.per_minute) {
+            Some((first, last)) => (last - first) as usize,
+            None => return self.merged_minutes,
+        };
+        span.saturating_add(1).saturating_add(self.merged_minutes)
+    }
+
+    /// Returns the average number of messages per minute over `minutes()`
+    pub fn messages_per_minute(&self) -> f64 {
+        match self.minutes() {
+            0 => 0.0,
+            minutes => self.total as f64 / minutes as f64,
+        }
+    }
+
+    /// Returns the `n` users that sent the most messages, with how many they sent and their favorite emotes
+    pub fn top_chatters(&self, n: usize) -> Vec<ChatterCount> {
+        let mut chatters: Vec<&Chatter> = self.chatters.values().collect();
+        chatters.sort_by(|a, b| {
+            b.messages
+                .cmp(&a.messages)
+                .then_with(|| a.display_name.cmp(&b.display_name))
+        });
+        chatters
+            .into_iter()
+            .take(n)
+            .map(|chatter| ChatterCount {
+                name: chatter.display_name.to_owned(),
+                messages: chatter.messages,
+                favorite_emotes: top_counts(&chatter.emotes, FAVORITE_EMOTES),
+            })
+            .collect()
+    }
+
+    /// Returns the `n` most used emotes, with how many times they were used
+    pub fn top_emotes(&self, n: usize) -> Vec<EmoteCount> {
+        top_counts(&self.emotes, n)
+    }
+
+    /// Returns the `n` minutes with the most messages, busiest first
+    pub fn busiest_minutes(&self, n: usize) -> Vec<MinuteCount> {
+        let mut minutes: Vec<(u64, usize)> = self
+            .per_minute
+            .iter()
+            .map(|(minute, messages)| (*minute, *messages))
+            .collect();
+        minutes.sort_by(|(a_minute, a), (b_minute, b)| b.cmp(a).then(a_minute.cmp(b_minute)));
+        minutes
+            .into_iter()
+            .take(n)
+            .map(|(minute, messages)| MinuteCount {
+                minute,
+                timestamp: minute_time(minute),
+                messages,
+            })
+            .collect()
+    }
+
+    /// Returns the favorite emotes of every window that had any, in order
+    pub fn emote_windows(&self) -> Vec<WindowEmotes> {
+        self.emotes_per_window
+            .iter()
+            .map(|(window, emotes)| {
+                let start = *window as f64 * self.emote_window;
+                WindowEmotes {
+                    start,
+                    timestamp: format_time(start as u32),
+                    favorite_emotes: top_counts(emotes, FAVORITE_EMOTES),
+                }
+            })
+            .collect()
+    }
+
+    /// Summarizes the stats, with the `n` top chatters, emotes and busiest minutes
+    pub fn report<S: AsRef<str>>(&self, title: S, n: usize) -> StatsReport {
+        StatsReport {
+            title: title.as_ref().to_owned(),
+            total_messages: self.total,
+            unique_chatters: self.unique_chatters(),
+            messages_per_minute: self.messages_per_minute(),
+            top_chatters: self.top_chatters(n),
+            top_emotes: self.top_emotes(n),
+            busiest_minutes: self.busiest_minutes(n),
+            per_minute: self.per_minute.to_owned(),
+            emote_windows: self.emote_windows(),
+        }
+    }
+}
+
+fn add_counts(counts: &mut HashMap<String, usize>, other: &HashMap<String, usize>) {
+    for (name, count) in other {
+        *counts.entry(name.to_owned()).or_insert(0) += count
+    }
+}
+
+/// Returns the `n` names of `counts` with the highest counts, highest first
+fn top_counts(counts: &HashMap<String, usize>, n: usize) -> Vec<EmoteCount> {
+    let mut counts: Vec<(&String, &usize)> = counts.iter().collect();
+    counts.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then_with(|| a_name.cmp(b_name)));
+    counts
+        .into_iter()
+        .take(n)
+        .map(|(name, uses)| EmoteCount {
+            name: name.to_owned(),
+            uses: *uses,
+        })
+        .collect()
+}
+
+/// Returns the first and the last minute of `per_minute`, if it has any
+fn minute_range(per_minute: &BTreeMap<u64, usize>) -> Option<(u64, u64)> {
+    let first = *per_minute.keys().next()?;
+    let last = *per_minute.keys().next_back()?;
+    Some((first, last))
+}
+
+/// Returns the start of `minute` as `HH:MM:SS`
+fn minute_time(minute: u64) -> String {
+    format_time(minute.saturating_mul(60).min(u64::from(u32::MAX)) as u32)
+}
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Returns `per_minute` as a sparkline of at most `width` characters, each covering the same number of minutes
+///
+/// The line starts at the first minute of `per_minute` and ends at the last
+pub fn sparkline(per_minute: &BTreeMap<u64, usize>, width: usize) -> String {
+    let (first, last) = match minute_range(per_minute) {
+        Some(range) if width > 0 => range,
+        _ => return String::new(),
+    };
+    let minutes = (last - first).saturating_add(1);
+    let minutes_per_char = minutes.div_ceil(width as u64);
+    let mut buckets = vec![0; minutes.div_ceil(minutes_per_char) as usize];
+    for (minute, count) in per_minute {
+        buckets[((minute - first) / minutes_per_char) as usize] += count
+    }
+    let max = buckets.iter().copied().max().unwrap_or(0).max(1);
+    buckets
+        .iter()
+        .map(|count| {
+            if *count == 0 {
+                ' '
+            } else {
+                SPARKS[(count * (SPARKS.len() - 1)).div_ceil(max)]
+            }
+        })
+        .collect()
+}
+
+impl StatsReport {
+    /// Prints the report to console, along with a sparkline of the activity over the video
+    pub fn print(&self) {
+        println!("\n{}", self.title);
+        println!("  Messages:         {}", self.total_messages);
+        println!("  Unique chatters:  {}", self.unique_chatters);
+        println!("  Messages/minute:  {:.1}", self.messages_per_minute);
+        if !self.top_chatters.is_empty() {
+            println!("  Top chatters:");
+            for (rank, chatter) in self.top_chatters.iter().enumerate() {
+                if chatter.favorite_emotes.is_empty() {
+                    println!(
+                        "    {:>2}. {} ({})",
+                        rank + 1,
+                        chatter.name,
+                        chatter.messages
+                    )
+                } else {
+                    println!(
+                        "    {:>2}. {} ({}) - {}",
+                        rank + 1,
+                        chatter.name,
+                        chatter.messages,
+                        list_emotes(&chatter.favorite_emotes)
+                    )
+                }
+            }
+        }
+        if !self.top_emotes.is_empty() {
+            println!("  Top emotes:");
+            for (rank, emote) in self.top_emotes.iter().enumerate() {
+                println!("    {:>2}. {} ({})", rank + 1, emote.name, emote.uses)
+            }
+        }
+        if !self.busiest_minutes.is_empty() {
+            println!("  Busiest minutes:");
+            for minute in &self.busiest_minutes {
+                println!("    [{}] {}", minute.timestamp, minute.messages)
+            }
+        }
+        if !self.emote_windows.is_empty() {
+            println!("  Favorite emotes over time:");
+            for window in &self.emote_windows {
+                println!(
+                    "    [{}] {}",
+                    window.timestamp,
+                    list_emotes(&window.favorite_emotes)
+                )
+            }
+        }
+        let width = match terminal::size() {
+            Ok((columns, _)) if output::use_colors() => usize::from(columns).saturating_sub(4),
+            _ => 80,
+        };
+        //the chat may have only been read from `--from`, so the line starts at the first message
+        let (first, last) = match minute_range(&self.per_minute) {
+            Some(range) => range,
+            None => return,
+        };
+        let line = sparkline(&self.per_minute, width.max(1));
+        println!(
+            "  Activity ({} - {}):",
+            minute_time(first),
+            minute_time(last.saturating_add(1))
+        );
+        if output::use_colors() {
+            execute!(
+                stdout(),
+                Print("  "),
+                SetForegroundColor(Color::Magenta),
+                Print(line),
+                ResetColor,
+                Print("\n")
+            )
+            .unwrap();
+        } else {
+            println!("  {}", line)
+        }
+    }
+}
+
+fn list_emotes(emotes: &[EmoteCount]) -> String {
+    emotes
+        .iter()
+        .map(|emote| format!("{} ({})", emote.name, emote.uses))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Reads the chat of `video` within the range of `query`, counting every message that matches
+///
+/// Emotes are counted in windows of `emote_window` seconds
+pub fn video_stats<V: VideoSource>(
+    video: &V,
+    query: &Query,
+    emote_window: f64,
+) -> Result<ChatStats> {
+    let mut stats = ChatStats::with_emote_window(emote_window);
+    for selected in query.select(query.read(video)) {
+        let selected = selected?;
+        if !selected.is_context {
+            stats.add(&selected.message)
+        }
+    }
+    Ok(stats)
+}
+
+/// Writes `report` in the current `output::Format`, as text or as a single JSON object
+pub fn write_report(report: &StatsReport) -> Result<()> {
+    output::write_record(report, || report.print())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::tests::message;
+
+    #[test]
+    fn only_minutes_with_messages_are_kept() {
+        let mut stats = ChatStats::new();
+        for offset in [30.0, 45.0, 150.0, 1e15, f64::INFINITY] {
+            stats.add(&message(offset, "alice", "hi"))
+        }
+        assert_eq!(stats.per_minute.len(), 4);
+        assert_eq!(stats.busiest_minutes(1)[0].minute, 0);
+        assert_eq!(stats.busiest_minutes(1)[0].messages, 2);
+        assert_eq!(stats.minutes(), usize::MAX);
+        assert_eq!(sparkline(&stats.per_minute, 10).chars().count(), 10);
+    }
+
+    #[test]
+    fn sparkline_starts_at_the_first_minute() {
+        let per_minute: BTreeMap<u64, usize> =
+            [(10, 1), (11, 4), (13, 2)].iter().copied().collect();
+        assert_eq!(sparkline(&per_minute, 80), "▃█ ▅");
+        assert_eq!(sparkline(&per_minute, 2), "█▄");
+        assert_eq!(sparkline(&BTreeMap::new(), 80), "");
+    }
+}
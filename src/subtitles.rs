@@ -0,0 +1,391 @@
+//! Turns chat into subtitle tracks, to watch a downloaded video with its chat overlaid
+use crate::{
+    chat::ChatMessage,
+    error::{Error, Result},
+};
+use std::{collections::VecDeque, io::Write};
+
+/// The subtitle formats chat can be written as
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubtitleFormat {
+    /// SubRip, `.srt`
+    Srt,
+    /// WebVTT, `.vtt`
+    WebVtt,
+    /// Advanced SubStation Alpha, `.ass`, which can color each user's name
+    Ass,
+}
+
+/// How chat is laid out on screen
+#[derive(Clone, Debug)]
+pub struct SubtitleOptions {
+    /// Seconds that each message stays on screen
+    pub duration: f64,
+    /// The most messages on screen at once; older messages are pushed off by newer ones
+    pub max_lines: usize,
+    /// Whether to color each user's name with their chat color, which only `SubtitleFormat::Ass` supports
+    pub user_colors: bool,
+}
+
+impl Default for SubtitleOptions {
+    fn default() -> Self {
+        SubtitleOptions {
+            duration: 5.0,
+            max_lines: 5,
+            user_colors: true,
+        }
+    }
+}
+
+/// Writes `messages` to `writer` as a subtitle track in `format`, returning how many messages were written
+///
+/// `messages` must be in the order they were sent, as every source returns them.
+/// Options that would put nothing on screen, a `duration` that is not positive or a `max_lines` of `0`, are an `Error::Parse`
+pub fn write_subtitles<I, W>(
+    messages: I,
+    format: SubtitleFormat,
+    options: &SubtitleOptions,
+    writer: W,
+) -> Result<usize>
+where
+    I: IntoIterator<Item = Result<ChatMessage>>,
+    W: Write,
+{
+    if !(options.duration > 0.0 && options.duration.is_finite()) {
+        return Err(Error::Parse(format!(
+            "a duration of {} seconds, which must be positive",
+            options.duration
+        )));
+    }
+    if options.max_lines == 0 {
+        return Err(Error::Parse(
+            "a max_lines of 0, which must be at least 1".to_owned(),
+        ));
+    }
+    let mut track = Track {
+        format,
+        options,
+        writer,
+        cues: 0,
+        now: 0.0,
+        visible: VecDeque::new(),
+    };
+    track.header()?;
+    let mut written = 0;
+    for message in messages {
+        track.push(message?)?;
+        written += 1
+    }
+    track.advance(f64::INFINITY)?;
+    track.writer.flush()?;
+    Ok(written)
+}
+
+struct Track<'a, W: Write> {
+    format: SubtitleFormat,
+    options: &'a SubtitleOptions,
+    writer: W,
+    /// How many cues have been written so far
+    cues: usize,
+    /// The time up to which the track has been written
+    now: f64,
+    /// The messages on screen along with the time they leave it, oldest first
+    visible: VecDeque<(f64, ChatMessage)>,
+}
+
+impl<'a, W: Write> Track<'a, W> {
+    fn header(&mut self) -> Result<()> {
+        match self.format {
+            SubtitleFormat::Srt => {}
+            SubtitleFormat::WebVtt => write!(self.writer, "WEBVTT\n\n")?,
+            SubtitleFormat::Ass => write!(
+                self.writer,
+                "[Script Info]\n\
+                 ScriptType: v4.00+\n\
+                 PlayResX: 1280\n\
+                 PlayResY: 720\n\
+                 WrapStyle: 0\n\
+                 \n\
+                 [V4+ Styles]\n\
+                 Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n\
+                 Style: Default,Arial,24,&H00FFFFFF,&H00FFFFFF,&H00000000,&H80000000,0,0,0,0,100,100,0,0,1,2,0,7,20,20,20,1\n\
+                 \n\
+                 [Events]\n\
+                 Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n"
+            )?,
+        }
+        Ok(())
+    }
+
+    /// Puts `message` on screen at its offset
+    fn push(&mut self, message: ChatMessage) -> Result<()> {
+        let start = message.offset.max(self.now);
+        self.advance(start)?;
+        self.cue(start)?;
+        self.visible
+            .push_back((start + self.options.duration, message));
+        while self.visible.len() > self.options.max_lines {
+            self.visible.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Writes cues up to `time`, taking messages off screen as their time runs out
+    fn advance(&mut self, time: f64) -> Result<()> {
+        //every message stays on screen for the same duration, so the oldest is always the first to leave
+        while let Some(end) = self.visible.front().map(|(end, _)| *end) {
+            if end > time {
+                break;
+            }
+            self.cue(end)?;
+            while self.visible.front().filter(|(e, _)| *e <= end).is_some() {
+                self.visible.pop_front();
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a cue showing what is currently on screen, from `now` until `end`
+    fn cue(&mut self, end: f64) -> Result<()> {
+        let start = self.now;
+        self.now = end;
+        if self.visible.is_empty() || end <= start {
+            return Ok(());
+        }
+        self.cues += 1;
+        match self.format {
+            SubtitleFormat::Srt => {
+                let lines: Vec<String> = self
+                    .visible
+                    .iter()
+                    .map(|(_, message)| {
+                        format!(
+                            "{}: {}",
+                            escape_srt(&message.display_name),
+                            escape_srt(&message.body)
+                        )
+                    })
+                    .collect();
+                write!(
+                    self.writer,
+                    "{}\n{} --> {}\n{}\n\n",
+                    self.cues,
+                    timestamp(start, ','),
+                    timestamp(end, ','),
+                    lines.join("\n")
+                )?
+            }
+            SubtitleFormat::WebVtt => {
+                let lines: Vec<String> = self
+                    .visible
+                    .iter()
+                    .map(|(_, message)| {
+                        format!(
+                            "<v {}>{}</v>",
+                            escape_vtt(&message.display_name),
+                            escape_vtt(&message.body)
+                        )
+                    })
+                    .collect();
+                write!(
+                    self.writer,
+                    "{} --> {}\n{}\n\n",
+                    timestamp(start, '.'),
+                    timestamp(end, '.'),
+                    lines.join("\n")
+                )?
+            }
+            SubtitleFormat::Ass => {
+                let lines: Vec<String> = self
+                    .visible
+                    .iter()
+                    .map(|(_, message)| {
+                        let name = escape_ass(&message.display_name);
+                        let body = escape_ass(&message.body);
+                        if self.options.user_colors {
+                            let (r, g, b) = message.rgb();
+                            //ASS colors are written as blue, green, red
+                            format!(
+                                "{{\\c&H{:02X}{:02X}{:02X}&}}{}{{\\r}}: {}",
+                                b, g, r, name, body
+                            )
+                        } else {
+                            format!("{}: {}", name, body)
+                        }
+                    })
+                    .collect();
+                writeln!(
+                    self.writer,
+                    "Dialogue: 0,{},{},Default,,0,0,0,,{}",
+                    ass_timestamp(start),
+                    ass_timestamp(end),
+                    lines.join("\\N")
+                )?
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats `seconds` as `HH:MM:SS{separator}mmm`, as used by SRT and WebVTT
+fn timestamp(seconds: f64, separator: char) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000
+    )
+}
+
+/// Formats `seconds` as `H:MM:SS.cc`, as used by ASS
+fn ass_timestamp(seconds: f64) -> String {
+    let centis = (seconds * 100.0).round() as u64;
+    format!(
+        "{}:{:02}:{:02}.{:02}",
+        centis / 360_000,
+        centis / 6000 % 60,
+        centis / 100 % 60,
+        centis % 100
+    )
+}
+
+/// Joins the lines of `text` with spaces, since a line break could end a cue or be read as one of its timings
+fn single_line(text: &str) -> String {
+    text.replace("\r\n", " ").replace(&['\r', '\n'][..], " ")
+}
+
+/// SRT has no way to escape the `-->` of a timing line, so it is replaced with a lookalike
+fn escape_srt(text: &str) -> String {
+    single_line(text).replace("-->", "\u{2192}")
+}
+
+fn escape_vtt(text: &str) -> String {
+    single_line(text)
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// ASS has no way to escape override blocks or line breaks, so they are replaced with lookalikes
+fn escape_ass(text: &str) -> String {
+    single_line(text)
+        .replace('{', "(")
+        .replace('}', ")")
+        .replace('\\', "\u{29F5}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::tests::message;
+
+    fn subtitles(
+        messages: Vec<ChatMessage>,
+        format: SubtitleFormat,
+        options: &SubtitleOptions,
+    ) -> String {
+        let mut written = Vec::new();
+        let count =
+            write_subtitles(messages.into_iter().map(Ok), format, options, &mut written).unwrap();
+        assert_eq!(count, 4);
+        String::from_utf8(written).unwrap()
+    }
+
+    fn chat() -> Vec<ChatMessage> {
+        vec![
+            message(0.0, "a", "one"),
+            message(1.0, "b", "two"),
+            message(2.0, "c", "three"),
+            message(10.0, "d", "four"),
+        ]
+    }
+
+    #[test]
+    fn cues_change_whenever_a_message_comes_or_goes() {
+        let options = SubtitleOptions {
+            max_lines: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            subtitles(chat(), SubtitleFormat::Srt, &options),
+            "1\n00:00:00,000 --> 00:00:01,000\na: one\n\n\
+             2\n00:00:01,000 --> 00:00:02,000\na: one\nb: two\n\n\
+             3\n00:00:02,000 --> 00:00:06,000\nb: two\nc: three\n\n\
+             4\n00:00:06,000 --> 00:00:07,000\nc: three\n\n\
+             5\n00:00:10,000 --> 00:00:15,000\nd: four\n\n"
+        );
+    }
+
+    #[test]
+    fn webvtt_is_escaped() {
+        let mut messages = chat();
+        messages[3].body = "<b> & co".to_owned();
+        let options = SubtitleOptions::default();
+        let track = subtitles(messages, SubtitleFormat::WebVtt, &options);
+        assert!(track.starts_with("WEBVTT\n\n00:00:00.000 --> 00:00:01.000\n<v a>one</v>\n\n"));
+        assert!(track.ends_with("00:00:10.000 --> 00:00:15.000\n<v d>&lt;b&gt; &amp; co</v>\n\n"));
+    }
+
+    #[test]
+    fn line_breaks_and_timings_cannot_be_written_in_a_cue() {
+        let mut messages = chat();
+        messages[0].display_name = "a\nb".to_owned();
+        messages[0].body = "x\r\n\n00:00:09,000 --> 00:00:10,000\ry".to_owned();
+        let options = SubtitleOptions::default();
+        let srt = subtitles(messages.to_owned(), SubtitleFormat::Srt, &options);
+        assert!(srt.starts_with(
+            "1\n00:00:00,000 --> 00:00:01,000\na b: x  00:00:09,000 \u{2192} 00:00:10,000 y\n\n"
+        ));
+        //one timing line for each of the 6 cues
+        assert_eq!(srt.matches("-->").count(), 6);
+        let vtt = subtitles(messages, SubtitleFormat::WebVtt, &options);
+        assert!(vtt.contains("\n<v a b>x  00:00:09,000 --&gt; 00:00:10,000 y</v>\n"));
+    }
+
+    #[test]
+    fn ass_colors_each_name() {
+        let mut messages = chat();
+        messages[0].color = Some("#FF8000".to_owned());
+        messages[0].body = "{\\b1}".to_owned();
+        let options = SubtitleOptions::default();
+        let track = subtitles(messages, SubtitleFormat::Ass, &options);
+        assert!(track.contains(
+            "Dialogue: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,{\\c&H0080FF&}a{\\r}: (\u{29F5}b1)\n"
+        ));
+        let options = SubtitleOptions {
+            user_colors: false,
+            ..Default::default()
+        };
+        let track = subtitles(chat(), SubtitleFormat::Ass, &options);
+        assert!(track.contains("Dialogue: 0,0:00:10.00,0:00:15.00,Default,,0,0,0,,d: four\n"));
+    }
+
+    #[test]
+    fn options_that_show_nothing_are_rejected() {
+        for options in [
+            SubtitleOptions {
+                duration: 0.0,
+                ..Default::default()
+            },
+            SubtitleOptions {
+                duration: -5.0,
+                ..Default::default()
+            },
+            SubtitleOptions {
+                max_lines: 0,
+                ..Default::default()
+            },
+        ] {
+            let result = write_subtitles(
+                chat().into_iter().map(Ok),
+                SubtitleFormat::Srt,
+                &options,
+                Vec::new(),
+            );
+            assert!(matches!(result, Err(Error::Parse(_))), "{:?}", options)
+        }
+    }
+}
@@ -1,76 +1,59 @@
+use crate::{
+    error::{Error, Result},
+    http::{transport, HttpRequest},
+};
 use crossterm::{
     execute,
     style::{Color, Print, ResetColor, SetForegroundColor},
 };
 use lazy_static::lazy_static;
-use regex::{Error, Regex};
-use reqwest::blocking::Client;
+use regex::{Regex, RegexBuilder};
+use serde::de::DeserializeOwned;
 use std::{
     fmt::Display,
-    io::{stdin, stdout, Write},
-    num::{ParseFloatError, ParseIntError},
-    process::exit,
+    io::{stderr, IsTerminal},
+    num::ParseIntError,
 };
 
-pub(crate) fn get_input() -> String {
-    let mut input = String::new();
-    if let Err(e) = stdout().flush() {
-        exit_error(e);
-    }
-    if let Err(e) = stdin().read_line(&mut input) {
-        exit_error(e);
-    }
-    input.trim_end_matches(&['\r', '\n'][..]).to_owned()
-}
-
-pub(crate) const CLIENT_ID: &str = "kimne78kx3ncx6brgo4mv6wki5h1ko";
+pub const CLIENT_ID: &str = "kimne78kx3ncx6brgo4mv6wki5h1ko";
 lazy_static! {
-    pub(crate) static ref CLIENT: Client = Client::new();
     static ref USERNAME_VALIDATE: Regex = Regex::new(r#"^[a-zA-Z0-9][\w]{3,24}$"#).unwrap();
 }
 
-pub(crate) fn clean_quotes<S: AsRef<str>>(str: S) -> String {
-    str.as_ref()
-        .trim_start_matches('"')
-        .trim_end_matches('"')
-        .to_string()
-}
-
-pub(crate) fn format_time_string<S: AsRef<str>>(seconds: S) -> Result<String, ParseFloatError> {
-    let seconds: u32 = match seconds.as_ref().parse::<f32>() {
-        Ok(seconds) => seconds as u32,
-        Err(e) => return Err(e),
-    };
-    Ok(format_time(seconds))
+/// Sends `request` through the current `http::Transport`, returning the body of the response
+///
+/// Unsuccessful HTTP statuses are turned into an `Error`
+pub fn send(request: HttpRequest) -> Result<String> {
+    let response = transport().execute(&request)?;
+    let url = request.url;
+    match response.status {
+        200..=299 => Ok(response.body),
+        401 | 403 => Err(Error::AuthRequired(url)),
+        404 => Err(Error::NotFound(url)),
+        status => Err(Error::Status { url, status }),
+    }
 }
 
-/// Function to call println! on all `String`s in a Vec whilst emptying it
-pub(crate) fn print_queue<S: AsRef<str>>(comment_queue: &mut Vec<S>) {
-    for comment in comment_queue.iter() {
-        println!("{}", comment.as_ref())
-    }
-    comment_queue.clear()
+/// Sends `request` and decodes the JSON response as a `T`
+///
+/// A response that does not match `T` results in an `Error::Schema` that names the URL and the mismatch
+pub fn send_json<T: DeserializeOwned>(request: HttpRequest) -> Result<T> {
+    let url = request.url.to_owned();
+    let text = send(request)?;
+    serde_json::from_str(&text).map_err(|e| Error::Schema(format!("{}: {}", url, e)))
 }
 
-pub(crate) fn hex_to_rgb<S: AsRef<str>>(hex: S) -> Result<Color, ParseIntError> {
+pub fn hex_to_rgb<S: AsRef<str>>(hex: S) -> std::result::Result<Color, ParseIntError> {
     let hex = hex.as_ref().trim_start_matches('#');
     const RADIX: u32 = 16;
-    let r = match u8::from_str_radix(&hex[0..2], RADIX) {
-        Ok(r) => r,
-        Err(e) => return Err(e),
-    };
-    let g = match u8::from_str_radix(&hex[2..4], RADIX) {
-        Ok(g) => g,
-        Err(e) => return Err(e),
-    };
-    let b = match u8::from_str_radix(&hex[4..6], RADIX) {
-        Ok(b) => b,
-        Err(e) => return Err(e),
-    };
+    //a range that is out of bounds or splits a character fails to parse instead of panicking
+    let r = u8::from_str_radix(hex.get(0..2).unwrap_or_default(), RADIX)?;
+    let g = u8::from_str_radix(hex.get(2..4).unwrap_or_default(), RADIX)?;
+    let b = u8::from_str_radix(hex.get(4..6).unwrap_or_default(), RADIX)?;
     Ok(Color::parse_ansi(&format!("2;{};{};{}", r, g, b)).unwrap())
 }
 
-pub(crate) fn format_time(seconds: u32) -> String {
+pub fn format_time(seconds: u32) -> String {
     let mut hours = (seconds / (60 * 60)).to_string();
     if hours.len() == 1 {
         hours = format!("0{}", hours);
@@ -83,39 +66,69 @@ pub(crate) fn format_time(seconds: u32) -> String {
     if seconds.len() == 1 {
         seconds = format!("0{}", seconds);
     }
-    return format!("{}:{}:{}", hours, minutes, seconds);
+    format!("{}:{}:{}", hours, minutes, seconds)
 }
 
-pub(crate) fn get_filter() -> Result<Regex, Error> {
-    print!("(RegExp) Please enter a phrase you would like to search for >>> ");
-    let re = get_input();
-    Regex::new(&format!(r"(?i)({})", re))
+/// Builds the case insensitive filter that chat is searched with, matching everything when there is no `pattern`
+pub fn build_filter<S: AsRef<str>>(pattern: Option<S>) -> Result<Regex> {
+    build_filter_with(pattern, false, false)
 }
 
-pub(crate) fn is_valid_username<S: AsRef<str>>(username: S) -> bool {
-    USERNAME_VALIDATE.is_match(username.as_ref())
+/// Identical to `build_filter()`, except that `pattern` can be matched literally and with case
+pub fn build_filter_with<S: AsRef<str>>(
+    pattern: Option<S>,
+    fixed_string: bool,
+    case_sensitive: bool,
+) -> Result<Regex> {
+    let pattern = match &pattern {
+        None => return Ok(Regex::new("")?),
+        Some(pattern) if fixed_string => regex::escape(pattern.as_ref()),
+        Some(pattern) => pattern.as_ref().to_owned(),
+    };
+    Ok(RegexBuilder::new(&pattern)
+        .case_insensitive(!case_sensitive)
+        .build()?)
 }
 
-pub(crate) fn args_filter<S: AsRef<str> + Display, A: Iterator<Item = S>>(
-    args: &mut A,
-) -> Result<Regex, Error> {
-    match args.next() {
-        None => Regex::new(&String::new()),
-        Some(re) => Regex::new(&format!(r#"(?i)({})"#, re)),
+/// Parses a time into seconds, written either as `HH:MM:SS`, `MM:SS` or a number of seconds
+///
+/// Only the leading field may reach 60 or more, so `90:00` is read but `1:99:99` is not
+pub fn parse_time<S: AsRef<str>>(time: S) -> Result<u32> {
+    let time = time.as_ref().trim();
+    let invalid = || Error::Parse(format!("'{}' as HH:MM:SS", time));
+    let mut seconds: u32 = 0;
+    for (i, part) in time.split(':').enumerate() {
+        let value = part.parse::<u32>()?;
+        if i >= 3 || (i > 0 && value >= 60) {
+            return Err(invalid());
+        }
+        seconds = seconds
+            .checked_mul(60)
+            .and_then(|seconds| seconds.checked_add(value))
+            .ok_or_else(invalid)?;
     }
+    Ok(seconds)
 }
-pub(crate) fn extract_digits<S: AsRef<str>>(s: S) -> u32 {
-    s.as_ref()
+
+pub fn is_valid_username<S: AsRef<str>>(username: S) -> bool {
+    USERNAME_VALIDATE.is_match(username.as_ref())
+}
+
+pub fn extract_digits<S: AsRef<str>>(s: S) -> Result<u32> {
+    Ok(s.as_ref()
         .chars()
         .filter(|c| c.is_numeric())
         .collect::<String>()
-        .parse()
-        .unwrap()
+        .parse()?)
 }
 
-pub(crate) fn error<S: Display>(message: S) {
+/// Prints `message` to stderr, in red when stderr is a terminal
+pub fn error<S: Display>(message: S) {
+    if !stderr().is_terminal() {
+        return eprint!("\n{}", message);
+    }
     execute!(
-        stdout(),
+        stderr(),
         SetForegroundColor(Color::Red),
         Print(format!("\n{}", message)),
         ResetColor
@@ -123,7 +136,32 @@ pub(crate) fn error<S: Display>(message: S) {
     .unwrap()
 }
 
-pub(crate) fn exit_error<S: Display>(message: S) -> ! {
-    error(message);
-    exit(-1)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_are_parsed_into_seconds() {
+        assert_eq!(parse_time("75").unwrap(), 75);
+        assert_eq!(parse_time("90:00").unwrap(), 5400);
+        assert_eq!(parse_time(" 1:20:05 ").unwrap(), 4805);
+        assert!(parse_time("1:2:3:4").is_err());
+        assert!(parse_time("1:-2").is_err());
+    }
+
+    #[test]
+    fn only_the_leading_field_may_reach_60() {
+        assert_eq!(parse_time("100:59").unwrap(), 6059);
+        assert!(parse_time("1:99:99").is_err());
+        assert!(parse_time("1:60").is_err());
+        assert!(parse_time("0:1:60").is_err());
+    }
+
+    #[test]
+    fn times_too_long_are_rejected_instead_of_overflowing() {
+        assert!(parse_time("99999999:0:0").is_err());
+        assert!(parse_time("4294967296").is_err());
+        assert_eq!(parse_time("4294967295").unwrap(), u32::MAX);
+        assert_eq!(parse_time("71582788:15").unwrap(), u32::MAX);
+    }
 }
@@ -1,20 +1,32 @@
 use crate::{
-    tools::{clean_quotes, CLIENT, CLIENT_ID},
+    error::{Error, Result},
+    source::ChannelSource,
+    twitch_gql::{self, GqlOperation},
+    twitch_models::VideoTowerData,
     twitch_vod::TwitchVOD,
 };
+use serde::Serialize;
 
-use serde_json::value::Value::Null;
-use serde_json::Value;
+/// The variables of `FilterableVideoTower_Videos`
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct VideoTowerVariables<'a> {
+    limit: u32,
+    channel_owner_login: &'a str,
+    broadcast_type: Option<&'a str>,
+    video_sort: &'a str,
+    cursor: &'a str,
+}
 
-pub(crate) struct TwitchChannel {
-    pub(crate) name: String,
+pub struct TwitchChannel {
+    pub name: String,
 }
 
 impl TwitchChannel {
     /// Creates a new `TwitchChannel` from an `&str` that represents the `name` of a channel
     ///
     /// A valid name would be "nasa", which can be derived from the channel URL: https://www.twitch.tv/nasa
-    pub(crate) fn new<S: AsRef<str>>(name: S) -> Self {
+    pub fn new<S: AsRef<str>>(name: S) -> Self {
         TwitchChannel {
             name: name.as_ref().to_owned(),
         }
@@ -23,67 +35,39 @@ impl TwitchChannel {
     /// Returns an list of `TwitchVOD`'s that are associated with a channel
     ///
     /// The max size of the returned `Vec<TwitchVOD>` will be 100, which is the limit for a single API query
-
-    pub(crate) fn vods(&self) -> Result<Vec<TwitchVOD>, String> {
-        let request = r#"[
-   {
-      "operationName":"FilterableVideoTower_Videos",
-      "variables":{
-         "limit":100,
-         "channelOwnerLogin":""#
-            .to_owned()
-            + &self.name
-            + r#"",
-         "broadcastType":null,
-         "videoSort":"TIME",
-         "cursor":""
-      },
-      "extensions":{
-         "persistedQuery":{
-            "version":1,
-            "sha256Hash":"a937f1d22e269e39a03b509f65a7490f9fc247d7f83d6ac1421523e3b68042cb"
-         }
-      }
-   }
-]"#;
-        let data: Value = match CLIENT
-            .post("https://gql.twitch.tv/gql")
-            .header("Client-Id", CLIENT_ID)
-            .body(request)
-            .send()
-        {
-            Ok(response) => response.json().unwrap(),
-            Err(e) => return Err(e.to_string()),
+    pub fn vods(&self) -> Result<Vec<TwitchVOD>> {
+        let operation = GqlOperation::new(
+            "FilterableVideoTower_Videos",
+            "a937f1d22e269e39a03b509f65a7490f9fc247d7f83d6ac1421523e3b68042cb",
+            &VideoTowerVariables {
+                limit: 100,
+                channel_owner_login: &self.name,
+                broadcast_type: None,
+                video_sort: "TIME",
+                cursor: "",
+            },
+        )?;
+        let data: VideoTowerData = twitch_gql::send(&operation)?;
+        let user = match data.user {
+            Some(user) => user,
+            None => return Err(Error::NotFound(self.name.to_owned())),
         };
-        let user = data
-            .get(0)
-            .unwrap()
-            .get("data")
-            .unwrap()
-            .get("user")
-            .unwrap();
-        if user == &Null {
-            return Err(format!("{} could not be found", self.name));
-        }
-        let vod_data = user
-            .get("videos")
-            .unwrap()
-            .get("edges")
-            .unwrap()
-            .as_array()
-            .unwrap();
-        let mut vods = Vec::with_capacity(vod_data.len());
-        for vod in vod_data {
-            let vod = vod.get("node").unwrap();
-            let id = clean_quotes(vod.get("id").unwrap().to_string())
-                .parse()
-                .unwrap();
-            let title = clean_quotes(vod.get("title").unwrap().to_string());
-            let animated_preview_url =
-                clean_quotes(vod.get("animatedPreviewURL").unwrap().to_string());
-            let v = TwitchVOD::new_unchecked(id, title, animated_preview_url);
+        let mut vods = Vec::with_capacity(user.videos.edges.len());
+        for vod in user.videos.edges {
+            let vod = vod.node;
+            let id = vod.id.parse()?;
+            let animated_preview_url = vod.animated_preview_url.unwrap_or_default();
+            let v = TwitchVOD::new_unchecked(id, vod.title, animated_preview_url);
             vods.push(v);
         }
         Ok(vods)
     }
 }
+
+impl ChannelSource for TwitchChannel {
+    type Video = TwitchVOD;
+
+    fn videos(&self) -> Result<Vec<TwitchVOD>> {
+        self.vods()
+    }
+}
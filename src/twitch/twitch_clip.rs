@@ -1,88 +1,69 @@
-use crate::tools::error;
 use crate::{
-    tools::{clean_quotes, CLIENT, CLIENT_ID},
+    error::{Error, Result},
     twitch_channel::TwitchChannel,
+    twitch_gql::{self, GqlOperation},
+    twitch_models::{Clip, ClipsCardsData},
 };
-use regex::Regex;
-use reqwest::Error;
-use serde_json::Value;
+use serde::Serialize;
 
-pub(crate) fn print_clips_from(channel: &TwitchChannel, filter: &Regex) {
-    let name = &channel.name;
-    let mut cursor = String::new();
-    loop {
-        let mut did_change = false;
-        let response = match get_clips_json(name, &cursor) {
-            Ok(response) => response,
-            Err(e) => {
-                return error(&e.to_string());
-            }
-        };
-        let clips = match response
-            .get(0)
-            .unwrap_or_else(|| panic!("{}", response.to_string()))
-            .get("data")
-            .unwrap()
-            .get("user")
-            .unwrap()
-            .get("clips")
-            .expect("Unknown Username!")
-            .get("edges")
-        {
-            None => continue,
-            Some(clips) => clips.as_array().unwrap(),
-        };
+impl TwitchChannel {
+    /// Returns every clip of the channel, following the pages of `ClipsCards__User` until none are left
+    pub fn clips(&self) -> Result<Vec<Clip>> {
+        let name = &self.name;
+        let mut cursor: Option<String> = None;
+        let mut all_clips = Vec::new();
+        loop {
+            let mut did_change = false;
+            let response = get_clips(name, cursor.as_deref())?;
+            let user = match response.user {
+                Some(user) => user,
+                None => return Err(Error::NotFound(name.to_owned())),
+            };
+            let clips = match user.clips {
+                Some(clips) => clips.edges,
+                None => return Err(Error::Schema("`clips` is missing".to_owned())),
+            };
 
-        for clip in clips {
-            let temp_cursor = clip.get("cursor").unwrap();
-            if !temp_cursor.is_null() {
-                cursor = format!(r#","cursor":{}"#, &temp_cursor);
-                did_change = true
+            for clip in clips {
+                if clip.cursor.is_some() {
+                    cursor = clip.cursor;
+                    did_change = true
+                }
+                all_clips.push(clip.node)
             }
-            let title = clip.get("node").unwrap().get("title").unwrap().to_string();
-            let url = clip.get("node").unwrap().get("url").unwrap().to_string();
-            if filter.is_match(&title) {
-                println!("[{}] {}", clean_quotes(&title), clean_quotes(&url))
+            if !did_change {
+                break;
             }
         }
-        if !did_change {
-            break;
-        }
+        Ok(all_clips)
     }
 }
 
-fn get_clips_json(name: &str, cursor: &str) -> Result<Value, Error> {
-    let request = r#"[
-   {
-      "operationName":"ClipsCards__User",
-      "variables":{
-         "login":""#
-        .to_owned()
-        + name
-        + r#"",
-         "limit":100,
-         "criteria":{
-            "filter":"ALL_TIME"
-         }"#
-        + cursor
-        + r#"
-      },
-      "extensions":{
-         "persistedQuery":{
-            "version":1,
-            "sha256Hash":"b73ad2bfaecfd30a9e6c28fada15bd97032c83ec77a0440766a56fe0bd632777"
-         }
-      }
-   }
-]"#;
-    match CLIENT
-        .post("https://gql.twitch.tv/gql")
-        .header("Client-Id", CLIENT_ID)
-        .header("Connection", "keep-alive")
-        .body(request)
-        .send()
-    {
-        Ok(result) => Ok(result.json().unwrap()),
-        Err(e) => Err(e),
-    }
+/// The variables of `ClipsCards__User`
+#[derive(Serialize)]
+struct ClipsCardsVariables<'a> {
+    login: &'a str,
+    limit: u32,
+    criteria: ClipsCriteria<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cursor: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct ClipsCriteria<'a> {
+    filter: &'a str,
+}
+
+fn get_clips(name: &str, cursor: Option<&str>) -> Result<ClipsCardsData> {
+    let operation = GqlOperation::new(
+        "ClipsCards__User",
+        "b73ad2bfaecfd30a9e6c28fada15bd97032c83ec77a0440766a56fe0bd632777",
+        &ClipsCardsVariables {
+            login: name,
+            limit: 100,
+            criteria: ClipsCriteria { filter: "ALL_TIME" },
+            cursor,
+        },
+    )?;
+    twitch_gql::send(&operation)
 }
@@ -0,0 +1,86 @@
+//! A minimal client for the persisted queries of Twitch GQL, `https://gql.twitch.tv/gql` by default
+//!
+//! Request bodies are always built by serializing `GqlOperation`s, never by pasting strings together,
+//! so that any user input placed in the variables is escaped correctly
+use crate::{
+    error::{Error, Result},
+    http::{endpoints, HttpRequest},
+    tools::{send_json, CLIENT_ID},
+    twitch_models::GqlResponse,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+/// A single GQL operation that refers to a query persisted on Twitch's side by its hash
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GqlOperation {
+    operation_name: String,
+    variables: Value,
+    extensions: Extensions,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Extensions {
+    persisted_query: PersistedQuery,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct PersistedQuery {
+    version: u32,
+    sha256_hash: String,
+}
+
+impl GqlOperation {
+    /// Creates a new `GqlOperation` from the name of the operation, the SHA-256 hash of its persisted query and its variables
+    pub fn new<V: Serialize>(
+        operation_name: &str,
+        sha256_hash: &str,
+        variables: &V,
+    ) -> Result<Self> {
+        Ok(GqlOperation {
+            operation_name: operation_name.to_owned(),
+            variables: serde_json::to_value(variables)?,
+            extensions: Extensions {
+                persisted_query: PersistedQuery {
+                    version: 1,
+                    sha256_hash: sha256_hash.to_owned(),
+                },
+            },
+        })
+    }
+}
+
+/// Sends every operation of `operations` in a single request
+///
+/// Returns the raw response of each operation, in the same order as `operations`, to be decoded with `data()`
+pub fn send_batch(operations: &[GqlOperation]) -> Result<Vec<Value>> {
+    let responses: Vec<Value> = send_json(
+        HttpRequest::post(&endpoints().twitch_gql)
+            .header("Client-Id", CLIENT_ID)
+            .header("Connection", "keep-alive")
+            .json(operations)?,
+    )?;
+    if responses.len() != operations.len() {
+        return Err(Error::Schema(format!(
+            "GQL returned {} responses to {} operations",
+            responses.len(),
+            operations.len()
+        )));
+    }
+    Ok(responses)
+}
+
+/// Sends a single operation and returns its decoded `data`
+pub fn send<T: DeserializeOwned>(operation: &GqlOperation) -> Result<T> {
+    let mut responses = send_batch(std::slice::from_ref(operation))?;
+    data(responses.remove(0))
+}
+
+/// Decodes the `data` of a single raw response returned by `send_batch()`
+pub fn data<T: DeserializeOwned>(response: Value) -> Result<T> {
+    let response: GqlResponse<T> = serde_json::from_value(response)?;
+    response.into_data()
+}
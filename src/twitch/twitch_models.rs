@@ -0,0 +1,204 @@
+//! Typed responses of the Twitch GQL operations used by this crate
+//!
+//! Only the fields that are read are declared, so that unrelated changes to a response do not break decoding
+use crate::error::{Error, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// A single response from `https://gql.twitch.tv/gql`, one of which is returned per operation sent
+#[derive(Deserialize, Debug)]
+pub struct GqlResponse<T> {
+    pub data: Option<T>,
+    #[serde(default)]
+    pub errors: Vec<GqlError>,
+}
+
+impl<T> GqlResponse<T> {
+    /// Returns the `data` of the response, or an `Error::Schema` with the reported errors if there is none
+    pub fn into_data(self) -> Result<T> {
+        match self.data {
+            Some(data) => Ok(data),
+            None => {
+                let messages: Vec<String> = self.errors.into_iter().map(|e| e.message).collect();
+                Err(Error::Schema(format!(
+                    "GQL returned no data: [{}]",
+                    messages.join(", ")
+                )))
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GqlError {
+    pub message: String,
+}
+
+/// A paginated list of `T`, as returned by most GQL operations
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Connection<T> {
+    pub edges: Vec<Edge<T>>,
+    pub page_info: Option<PageInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PageInfo {
+    pub has_next_page: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Edge<T> {
+    pub cursor: Option<String>,
+    pub node: T,
+}
+
+/// `FilterableVideoTower_Videos`
+#[derive(Deserialize, Debug)]
+pub struct VideoTowerData {
+    pub user: Option<VideoTowerUser>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VideoTowerUser {
+    pub videos: Connection<VideoTowerVideo>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoTowerVideo {
+    pub id: String,
+    pub title: String,
+    #[serde(rename = "animatedPreviewURL")]
+    pub animated_preview_url: Option<String>,
+}
+
+/// `ClipsCards__User`
+#[derive(Deserialize, Debug)]
+pub struct ClipsCardsData {
+    pub user: Option<ClipsCardsUser>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ClipsCardsUser {
+    pub clips: Option<Connection<Clip>>,
+}
+
+/// A clip of a channel, as returned by `TwitchChannel::clips()`
+#[derive(Deserialize, Debug, Clone)]
+pub struct Clip {
+    pub title: String,
+    pub url: String,
+}
+
+/// `ComscoreStreamingQuery`, `VideoMetadata` and `VideoPlayer_VODSeekbarPreviewVideo`, which all only return a `video`
+#[derive(Deserialize, Debug)]
+pub struct VideoData<T> {
+    pub video: Option<T>,
+}
+
+/// The `video` of `ComscoreStreamingQuery`
+#[derive(Deserialize, Debug)]
+pub struct ComscoreVideo {
+    pub title: String,
+}
+
+/// The `video` of `VideoMetadata`
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoMetadata {
+    pub broadcast_type: String,
+}
+
+/// The `video` of `VideoPlayer_VODSeekbarPreviewVideo`
+#[derive(Deserialize, Debug)]
+pub struct SeekbarPreviewVideo {
+    #[serde(rename = "seekPreviewsURL")]
+    pub seek_previews_url: String,
+}
+
+/// The `video` of `VideoCommentsByOffsetOrCursor`
+///
+/// The comments are kept as `Value`s so that they can be stored as the `raw` fields of a `ChatMessage`
+#[derive(Deserialize, Debug)]
+pub struct VideoComments {
+    pub comments: Option<Connection<Value>>,
+}
+
+/// A single comment of `VideoComments`
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Comment {
+    /// `None` when the account that left the comment no longer exists
+    pub commenter: Option<Commenter>,
+    #[serde(alias = "content_offset_seconds")]
+    pub content_offset_seconds: f64,
+    pub message: CommentMessage,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Commenter {
+    #[serde(alias = "_id")]
+    pub id: String,
+    #[serde(alias = "name")]
+    pub login: String,
+    #[serde(alias = "display_name")]
+    pub display_name: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CommentMessage {
+    #[serde(default)]
+    pub fragments: Vec<CommentFragment>,
+    /// The whole text of the comment, which only saved v5 comments have, used when there are no fragments
+    #[serde(default)]
+    pub body: Option<String>,
+    /// `None` for comments whose badges were not returned
+    #[serde(default, alias = "user_badges")]
+    pub user_badges: Option<Vec<UserBadge>>,
+    #[serde(default, alias = "user_color")]
+    pub user_color: Option<String>,
+    /// The bits cheered with the comment, when the response has them, otherwise the bits of its cheermote fragments,
+    /// or failing those of the `Cheer` cheers in its text, are counted
+    #[serde(default, alias = "bits_spent")]
+    pub bits_spent: Option<u32>,
+    /// The parameters of a user notice, such as `msg-id`, when the response has them
+    #[serde(default, alias = "user_notice_params")]
+    pub user_notice_params: Option<HashMap<String, Value>>,
+}
+
+/// A badge shown next to the commenter's name, such as `subscriber` version `12`
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserBadge {
+    #[serde(rename = "setID", alias = "_id")]
+    pub set_id: String,
+    pub version: String,
+}
+
+/// A piece of a `CommentMessage`, which is either plain text, a single emote or a single cheermote
+#[derive(Deserialize, Debug)]
+pub struct CommentFragment {
+    pub text: String,
+    /// `None` for plain text
+    #[serde(default, alias = "emoticon")]
+    pub emote: Option<FragmentEmote>,
+    /// `None` for anything but a cheer, such as `Cheer100`
+    #[serde(default)]
+    pub cheermote: Option<FragmentCheermote>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FragmentCheermote {
+    pub bits: u32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FragmentEmote {
+    #[serde(rename = "emoteID", alias = "emoticon_id")]
+    pub emote_id: String,
+}
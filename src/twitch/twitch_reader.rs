@@ -1,17 +1,15 @@
 use crate::{
-    tools::{args_filter, error, get_filter, get_input, is_valid_username},
-    twitch_channel::TwitchChannel,
-    twitch_clip::print_clips_from,
-    twitch_vod::TwitchVOD,
+    cli::{TwitchCommand, DEFAULT_CONCURRENCY},
+    export, get_filter, get_input, report, retry_once_then_skip, search,
 };
-use regex::Regex;
-use std::iter::Skip;
-use std::{
-    env::Args,
-    io::{stdin, stdout, Write},
-    sync::mpsc::{channel, Sender},
-    thread::{spawn, JoinHandle},
+use chat_reader::{
+    output,
+    source::print_videos,
+    tools::{build_filter, error, is_valid_username},
+    Error, Result, TwitchChannel, TwitchVOD, VideoSource,
 };
+use regex::Regex;
+use std::io::{stdin, stdout, Write};
 
 pub(crate) fn main() {
     loop {
@@ -43,51 +41,19 @@ fn get_clips() {
         Ok(filter) => filter,
         Err(e) => return error(e),
     };
-    print_clips_from(&channel, &filter);
-}
-
-pub(crate) fn args_channel(args: &mut Skip<Args>) {
-    let channel_name = match args.next() {
-        None => return error("-tc\n    ^^^\nNo channel name declared after `-tc`"),
-        Some(channel_name) => {
-            if !is_valid_username(&channel_name) {
-                let mut other_args = String::new();
-                for arg in args {
-                    other_args += " ";
-                    other_args += arg.as_ref()
-                }
-                return error(format!(
-                    "-tc {}{:?}\n    {arrows}\nerror: invalid channel name declared after `-tc`",
-                    channel_name,
-                    other_args,
-                    arrows = "^".repeat(channel_name.len())
-                ));
-            }
-            channel_name
-        }
-    };
-
-    let has_filter = args_has_filter(args);
-    let mut filter = Regex::new("(.*?)").unwrap(); //This is a valid pattern
-    if has_filter {
-        filter = match args_filter(args) {
-            Ok(filter) => filter,
-            Err(e) => return error(e),
-        };
+    if let Err(e) = print_clips(&channel, &filter) {
+        error(e)
     }
-    let ch = TwitchChannel::new(&channel_name);
-    let vods = match ch.vods() {
-        Ok(vods) => vods,
-        Err(e) => return error(e),
-    };
-    display_channel(vods, filter);
 }
 
-fn args_has_filter(args: &mut Skip<Args>) -> bool {
-    match args.next() {
-        None => false,
-        Some(label) => label.eq_ignore_ascii_case("-f"),
+/// Prints the title and URL of every clip of `channel` whose title matches `filter`
+fn print_clips(channel: &TwitchChannel, filter: &Regex) -> Result<()> {
+    for clip in channel.clips()? {
+        if filter.is_match(&clip.title) {
+            println!("[{}] {}", clip.title, clip.url)
+        }
     }
+    Ok(())
 }
 
 fn input_channel() {
@@ -117,69 +83,94 @@ fn input_channel() {
         Ok(filter) => filter,
         Err(e) => return error(e),
     };
-    display_channel(vods, filter)
-}
-
-fn display_channel(vods: Vec<TwitchVOD>, filter: Regex) {
-    let mut threads: Vec<(
-        (String, u32),
-        Sender<()>,
-        JoinHandle<()>,
-        JoinHandle<String>,
-    )> = Vec::new();
-    for vod in vods {
-        //The thread must own all the parameters
-        let (tx, rx) = channel();
-        let vod_thread = vod.to_owned();
-        let filter = filter.to_owned();
-        let chat_thread = spawn(move || vod_thread.print_chat(&filter, rx));
-        let vod_thread = vod.to_owned();
-        let url_thread = spawn(move || vod_thread.m3u8());
-        threads.push(((vod.title, vod.id), tx, chat_thread, url_thread));
-    }
-    for reader in threads {
-        let title = reader.0 .0;
-        let id = reader.0 .1;
-        let tx = reader.1;
-        let chat_thread = reader.2;
-        let url_thread = reader.3;
-
-        println!("\n{} v{}", title, id);
-        println!("{}", url_thread.join().unwrap());
-        tx.send(());
-        chat_thread.join().unwrap();
+    if let Err(e) = print_videos(
+        vods,
+        &filter.into(),
+        DEFAULT_CONCURRENCY.into(),
+        retry_once_then_skip(),
+    ) {
+        error(e)
     }
 }
 
-pub(crate) fn args_vod(args: &mut Skip<Args>) {
-    let vod_id: u32 = match args.next() {
-        None => return error("-tv\n    ^^^\nNo VOD ID declared after `-tv`"),
-        Some(vod_id) => match vod_id.parse() {
-            Ok(vod_id) => vod_id,
-            Err(e) => return error(e),
-        },
-    };
-
-    let vod = match TwitchVOD::new(vod_id) {
-        Ok(vod) => vod,
-        Err(e) => return error(e),
-    };
-    let filter;
-    if args_has_filter(args) {
-        filter = match args_filter(args) {
-            Ok(filter) => filter,
-            Err(e) => return error(e),
-        };
-    } else {
-        filter = Regex::new("(.*?)").unwrap()
+/// Runs a `twitch` command given on the command line
+pub(crate) fn run(command: TwitchCommand) -> Result<()> {
+    match command {
+        TwitchCommand::Vod {
+            id,
+            options,
+            export,
+        } => {
+            let query = options.prepare()?;
+            if let (Some(flag), Some(_)) = (options.report_flag(), export.export) {
+                return Err(Error::Parse(format!(
+                    "{} with --export, which cannot be used together",
+                    flag
+                )));
+            }
+            let vod = TwitchVOD::new(id)?;
+            if let Some(format) = export.export {
+                return export::export(&vod, &query, format, &export);
+            }
+            if options.stats {
+                return report::stats(&vod, &query, options.trend_window, options.top);
+            }
+            if options.highlights.highlights {
+                let highlights = (&options.highlights).into();
+                return report::highlights(&vod, &query, &highlights, options.top);
+            }
+            match vod.m3u8() {
+                Ok(url) => output::write_info(url),
+                Err(e) => error(format!("{}\n", e)),
+            }
+            vod.print_chat_blocking(&query)
+        }
+        TwitchCommand::Channel { name, options } => {
+            let query = options.prepare()?;
+            let vods = TwitchChannel::new(&name).vods()?;
+            //the list is kept to index the chat archived while reading it
+            let listed = vods.to_owned();
+            if options.stats {
+                report::channel_stats(
+                    &name,
+                    vods,
+                    &query,
+                    options.trend_window,
+                    options.top,
+                    options.concurrency.into(),
+                )?
+            } else if options.highlights.highlights {
+                report::channel_highlights(
+                    vods,
+                    &query,
+                    &(&options.highlights).into(),
+                    options.top,
+                    options.concurrency.into(),
+                )?
+            } else {
+                print_videos(
+                    vods,
+                    &query,
+                    options.concurrency.into(),
+                    retry_once_then_skip(),
+                )?
+            }
+            search::sync_index(&name, &listed)
+        }
+        TwitchCommand::Clips { name, filter } => {
+            let filter = build_filter(filter.as_ref())?;
+            print_clips(&TwitchChannel::new(&name), &filter)
+        }
     }
-    vod.print_chat_blocking(&filter)
 }
 
 fn input_vod() {
     print!("Input VOD ID >>> ");
     let vod_id = get_input();
-    let vod_id = vod_id.parse().unwrap();
+    let vod_id = match vod_id.parse() {
+        Ok(vod_id) => vod_id,
+        Err(e) => return error(e),
+    };
     let vod = match TwitchVOD::new(vod_id) {
         Ok(vod) => vod,
         Err(e) => return error(e),
@@ -188,6 +179,11 @@ fn input_vod() {
         Ok(filter) => filter,
         Err(e) => return error(e),
     };
-    println!("{}", vod.m3u8());
-    vod.print_chat_blocking(&filter)
+    match vod.m3u8() {
+        Ok(url) => println!("{}", url),
+        Err(e) => error(format!("{}\n", e)),
+    }
+    if let Err(e) = vod.print_chat_blocking(&filter.into()) {
+        error(e)
+    }
 }
@@ -1,51 +1,176 @@
-use crate::tools::{clean_quotes, exit_error, format_time_string, hex_to_rgb, CLIENT_ID};
-use crossterm::{
-    execute,
-    style::{Color, Print, ResetColor, SetForegroundColor},
+use crate::{
+    chat::{Badge, ChatMessage, Emote, Platform},
+    error::{Error, Result},
+    source::{Resumable, VideoSource},
+    twitch_gql::{self, GqlOperation},
+    twitch_models::{
+        Comment, ComscoreVideo, SeekbarPreviewVideo, VideoComments, VideoData, VideoMetadata,
+    },
 };
 use lazy_static::lazy_static;
 use regex::Regex;
-use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::{
-    io::stdout,
-    sync::mpsc::{channel, Receiver},
-};
+use std::collections::VecDeque;
+
 lazy_static! {
-    static ref CLIENT: Client = Client::new();
+    //the English messages that Twitch posts in the name of a user for their subscriptions, after their name.
+    //They are only used when the response has no `msg-id`, so notices posted in other languages are not recognized
+    static ref NOTICE_MATCHERS: [(&'static str, Regex); 5] = [
+        ("resub", Regex::new("^ subscribed (at Tier [0-9]|with Prime)\\. They've subscribed for [0-9]+ months").unwrap()),
+        ("sub", Regex::new("^ subscribed (at Tier [0-9]|with Prime)").unwrap()),
+        ("subgift", Regex::new("^ gifted a Tier [0-9] sub to ").unwrap()),
+        ("submysterygift", Regex::new("^ is gifting [0-9]+ Tier [0-9] Subs").unwrap()),
+        ("giftpaidupgrade", Regex::new("^ is continuing the Gift Sub they got from ").unwrap()),
+    ];
+    //a cheer written with the default `Cheer` cheermote, such as `Cheer100`
+    static ref CHEER_MATCHER: Regex = Regex::new("^(?i:cheer)([0-9]+)$").unwrap();
+}
+
+/// The variables of `ComscoreStreamingQuery`
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ComscoreVariables<'a> {
+    channel: &'a str,
+    clip_slug: &'a str,
+    is_clip: bool,
+    is_live: bool,
+    is_vod_or_collection: bool,
+    #[serde(rename = "vodID")]
+    vod_id: String,
+}
+
+/// The variables of `VideoPlayer_VODSeekbarPreviewVideo`
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SeekbarPreviewVariables {
+    include_private: bool,
+    #[serde(rename = "videoID")]
+    video_id: String,
+}
+
+/// The variables of `VideoCommentsByOffsetOrCursor`, which takes either a `content_offset_seconds` or a `cursor`
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct VideoCommentsVariables {
+    #[serde(rename = "videoID")]
+    video_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_offset_seconds: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cursor: Option<String>,
+}
+
+/// The variables of `VideoMetadata`
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct VideoMetadataVariables<'a> {
+    channel_login: &'a str,
+    #[serde(rename = "videoID")]
+    video_id: String,
 }
 
 #[derive(Clone)]
-pub(crate) struct TwitchVOD {
-    pub(crate) title: String,
-    pub(crate) id: u32,
+pub struct TwitchVOD {
+    pub title: String,
+    pub id: u32,
     animated_preview_url: String,
 }
 
-fn dump_comments(comments: &mut Vec<(String, String, String, Color)>) {
-    for comment in comments.iter_mut() {
-        let timestamp = &comment.0;
-        let display_name = &comment.1;
-        let message = &comment.2;
-        let user_color = comment.3;
-        execute!(
-            stdout(),
-            Print(format!("[{}][", timestamp)),
-            SetForegroundColor(user_color),
-            Print(String::from(display_name)),
-            ResetColor,
-            Print(format!("]: {}\n", message))
-        )
-        .unwrap();
+/// A lazy iterator over the comments of a `TwitchVOD`, created by `TwitchVOD::comments()`
+pub struct TwitchComments {
+    vod: TwitchVOD,
+    /// The next page to request, `None` once the last page has been requested
+    next_page: Option<PageRequest>,
+    /// The comments of the current page, each with the cursor of its edge
+    page: VecDeque<(ChatMessage, Option<String>)>,
+    /// The page that starts right after the last comment returned, `None` if it is not known
+    resume_at: Option<PageRequest>,
+}
+
+/// The ways that `VideoCommentsByOffsetOrCursor` can locate a page of comments, which is also where `TwitchComments` resume
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PageRequest {
+    /// The page of comments sent at, or right after, the given number of seconds into the VOD
+    Offset(u32),
+    /// The page after the comment with the given cursor
+    Cursor(String),
+}
+
+impl TwitchComments {
+    fn load_page(&mut self, request: PageRequest) -> Result<()> {
+        let (content_offset_seconds, cursor) = match request {
+            PageRequest::Offset(offset) => (Some(offset), None),
+            PageRequest::Cursor(cursor) => (None, Some(cursor)),
+        };
+        let operation = GqlOperation::new(
+            "VideoCommentsByOffsetOrCursor",
+            "b70a3591ff0f4e0313d126c6a1502d79a1c02baebb288227c582044aa76adf6a",
+            &VideoCommentsVariables {
+                video_id: self.vod.id.to_string(),
+                content_offset_seconds,
+                cursor,
+            },
+        )?;
+        let data: VideoData<VideoComments> = twitch_gql::send(&operation)?;
+        let comments = match data.video {
+            Some(video) => video.comments,
+            None => return Err(Error::NotFound(format!("VOD {}", self.vod.id))),
+        };
+        let comments = match comments {
+            Some(comments) => comments,
+            None => return Err(Error::Schema("`comments` is missing".to_owned())),
+        };
+        let has_next_page = match comments.page_info {
+            Some(page_info) => page_info.has_next_page,
+            None => false,
+        };
+        let mut last_cursor = None;
+        for edge in comments.edges {
+            let message = parse_comment(edge.node, &self.vod.id.to_string(), &self.vod.title)?;
+            self.page.push_back((message, edge.cursor.to_owned()));
+            last_cursor = edge.cursor;
+        }
+        if has_next_page {
+            self.next_page = last_cursor.map(PageRequest::Cursor);
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for TwitchComments {
+    type Item = Result<ChatMessage>;
+
+    /// Returns the next comment, requesting the next page when the current one runs out
+    ///
+    /// Once an `Err` has been returned, the iterator is finished
+    fn next(&mut self) -> Option<Result<ChatMessage>> {
+        while self.page.is_empty() {
+            let request = self.next_page.take()?;
+            if let Err(e) = self.load_page(request) {
+                self.page.clear();
+                return Some(Err(e));
+            }
+        }
+        let (message, cursor) = self.page.pop_front()?;
+        self.resume_at = cursor.map(PageRequest::Cursor);
+        Some(Ok(message))
+    }
+}
+
+impl Resumable for TwitchComments {
+    type Cursor = PageRequest;
+
+    fn cursor(&self) -> Option<PageRequest> {
+        self.resume_at.to_owned()
     }
-    comments.clear()
 }
 
 impl TwitchVOD {
     /// Creates a new `TwitchVOD` from a `u32` that represents an ID and an `&str` that represents the title
     ///
     /// The function will not check any values and may result in errors when calling other functions
-    pub(crate) fn new_unchecked(id: u32, title: String, animated_preview_url: String) -> Self {
+    pub fn new_unchecked(id: u32, title: String, animated_preview_url: String) -> Self {
         TwitchVOD {
             id,
             title,
@@ -55,164 +180,51 @@ impl TwitchVOD {
     /// Creates a new `TwitchVOD` from a `u32` that represents the ID of the VOD
     ///
     /// A valid ID would be `799499623`, which can be derived from the VOD URL: https://www.twitch.tv/videos/799499623
-    pub(crate) fn new(id: u32) -> Result<Self, String> {
-        let request = r#"[{
-      "operationName":"ComscoreStreamingQuery",
-      "variables":{
-         "channel":"",
-         "clipSlug":"",
-         "isClip":false,
-         "isLive":false,
-         "isVodOrCollection":true,
-         "vodID":""#
-            .to_owned()
-            + &id.to_string()
-            + r#""
-      },
-      "extensions":{
-         "persistedQuery":{
-            "version":1,
-            "sha256Hash":"e1edae8122517d013405f237ffcc124515dc6ded82480a88daef69c83b53ac01"
-         }
-      }
-   }]"#;
-        let data: Value = match CLIENT
-            .post("https://gql.twitch.tv/gql")
-            .header("Client-Id", CLIENT_ID)
-            .body(request)
-            .send()
-        {
-            Ok(response) => response,
-            Err(e) => return Err(e.to_string()),
-        }
-        .json()
-        .unwrap();
-        let title = clean_quotes(
-            &data
-                .get(0)
-                .unwrap()
-                .get("data")
-                .unwrap()
-                .get("video")
-                .unwrap()
-                .get("title")
-                .unwrap()
-                .to_string(),
-        );
+    pub fn new(id: u32) -> Result<Self> {
+        let operation = GqlOperation::new(
+            "ComscoreStreamingQuery",
+            "e1edae8122517d013405f237ffcc124515dc6ded82480a88daef69c83b53ac01",
+            &ComscoreVariables {
+                channel: "",
+                clip_slug: "",
+                is_clip: false,
+                is_live: false,
+                is_vod_or_collection: true,
+                vod_id: id.to_string(),
+            },
+        )?;
+        let data: VideoData<ComscoreVideo> = twitch_gql::send(&operation)?;
+        let title = match data.video {
+            Some(video) => video.title,
+            None => return Err(Error::NotFound(format!("VOD {}", id))),
+        };
         Ok(TwitchVOD {
             title,
             id,
             animated_preview_url: String::new(),
         })
     }
-    /// Identical function to `twitch_vod::print_chat()` except that no Receiver<()> is required.
-    ///
-    /// Comments will be printed as soon as they are parsed and will not remain in a queue
+    /// Returns an iterator over every comment left on the VOD, in the order they were sent
     ///
-    /// This is recommended for single thread use cases
-    pub(crate) fn print_chat_blocking(&self, filter: &Regex) {
-        let (tx, rx) = channel();
-        tx.send(()).unwrap(); //print immediately
-        self.print_chat(filter, rx)
+    /// Comments are requested lazily, one page at a time, by following the cursor of `VideoCommentsByOffsetOrCursor`
+    pub fn comments(&self) -> TwitchComments {
+        self.comments_from(0)
     }
 
-    /// Prints the chat to console from an individual `TwitchVOD`
-    ///
-    /// This required parameters are a `TwitchVOD` with a valid name, `Regex` filter, and `Receiver<bool>`
-    ///
-    /// All `comments: String` will be ran through the passed `Regex` and only comments that return a match to the filter will be displayed
+    /// Identical to `comments()`, except that the first page is requested at `offset` seconds into the VOD instead of at the start
     ///
-    /// The `rx: Receiver<bool>` is used to determine when the comments should be printed out
-    ///
-    /// By default, the outputs are queued into `comment_queue` and then will be allowed to print only when `rx` receives a boolean from a `Sender<bool>`
-    pub(crate) fn print_chat(&self, filter: &Regex, rx: Receiver<()>) {
-        let mut cursor = String::new();
-        let mut comment_queue: Vec<(String, String, String, Color)> = Vec::new();
-        let mut waiting_to_print = true;
-        loop {
-            let comment_json: Value = match CLIENT
-                .get(format!(
-                    "https://api.twitch.tv/v5/videos/{}/comments?cursor={}",
-                    self.id, cursor
-                ))
-                .header("Client-ID", CLIENT_ID)
-                .header("Connection", "keep-alive")
-                .send()
-            {
-                Ok(get) => match get.json() {
-                    Ok(json) => json,
-                    Err(e) => exit_error(&e.to_string()),
-                },
-                Err(e) => exit_error(&e.to_string()),
-            };
-            let comments = comment_json
-                .get("comments")
-                .unwrap_or_else(|| panic!("\nCould not find comments in data"))
-                .as_array()
-                .unwrap_or_else(|| panic!("\nChannel vod data could not be parsed as an array"));
-            for comment in comments {
-                let timestamp = match format_time_string(
-                    &comment
-                        .get("content_offset_seconds")
-                        .unwrap_or_else(|| {
-                            panic!("\nCould not find content_offset_seconds in comment")
-                        })
-                        .to_string(),
-                ) {
-                    Ok(timestamp) => timestamp,
-                    Err(e) => exit_error(&e.to_string()),
-                };
-                let display_name = clean_quotes(
-                    &comment
-                        .get("commenter")
-                        .unwrap_or_else(|| panic!("\nCould not find commenter in comment"))
-                        .get("display_name")
-                        .unwrap_or_else(|| panic!("\nCould not find display_name in commenter"))
-                        .to_string(),
-                );
-
-                let message = comment
-                    .get("message")
-                    .unwrap_or_else(|| panic!("\nCould not find message in comment"));
-                let body = clean_quotes(
-                    &message
-                        .get("body")
-                        .unwrap_or_else(|| panic!("\nCould not find body in message"))
-                        .to_string(),
-                );
-                if filter.is_match(&body) {
-                    let color = match message.get("user_color") {
-                        None => Color::Reset,
-                        Some(color) => {
-                            let c_string = clean_quotes(&color.to_string());
-                            if c_string.is_empty() {
-                                Color::Reset
-                            } else {
-                                match hex_to_rgb(&c_string) {
-                                    Ok(color) => color,
-                                    Err(e) => exit_error(&e.to_string()),
-                                }
-                            }
-                        }
-                    };
-                    comment_queue.push((timestamp, display_name, body, color))
-                }
-                if waiting_to_print {
-                    if rx.try_recv().is_ok() {
-                        waiting_to_print = false
-                    }
-                } else {
-                    dump_comments(&mut comment_queue)
-                }
-            }
-            match comment_json.get("_next") {
-                Some(_next) => cursor = clean_quotes(&_next.to_string()),
-                None => break,
-            }
-        }
-        if !comment_queue.is_empty() {
-            rx.recv().unwrap();
-            dump_comments(&mut comment_queue)
+    /// The first page may still hold a few comments sent shortly before `offset`
+    pub fn comments_from(&self, offset: u32) -> TwitchComments {
+        self.comments_at(PageRequest::Offset(offset))
+    }
+
+    /// Identical to `comments()`, except that reading resumes at `request`, as returned by `TwitchComments::cursor()`
+    pub fn comments_at(&self, request: PageRequest) -> TwitchComments {
+        TwitchComments {
+            vod: self.to_owned(),
+            next_page: Some(request.to_owned()),
+            page: VecDeque::new(),
+            resume_at: Some(request),
         }
     }
 
@@ -221,93 +233,247 @@ impl TwitchVOD {
     /// Requires video ID to be valid
     ///
     /// In special cases, such as for channel trailers, where M3U8's cannot be easily computed, the official VOD link is returned
-    pub(crate) fn m3u8(&self) -> String {
-        let mut preview_url = (&self.animated_preview_url).to_owned();
-        if preview_url.is_empty() {
-            let request = r#"[{
-      "operationName":"VideoPlayer_VODSeekbarPreviewVideo",
-      "variables":{
-         "includePrivate":false,
-         "videoID":""#
-                .to_owned()
-                + &self.id.to_string()
-                + r#""
-      },
-      "extensions":{
-         "persistedQuery":{
-            "version":1,
-            "sha256Hash":"07e99e4d56c5a7c67117a154777b0baf85a5ffefa393b213f4bc712ccaf85dd6"
-         }
-      }
-   }]"#;
-            let data: Value = CLIENT
-                .post("https://gql.twitch.tv/gql")
-                .header("Client-Id", CLIENT_ID)
-                .body(request)
-                .send()
-                .unwrap()
-                .json()
-                .unwrap();
-            preview_url = clean_quotes(
-                &data
-                    .get(0)
-                    .unwrap()
-                    .get("data")
-                    .unwrap()
-                    .get("video")
-                    .unwrap()
-                    .get("seekPreviewsURL")
-                    .unwrap()
-                    .to_string(),
-            );
+    pub fn m3u8(&self) -> Result<String> {
+        //both lookups are sent in a single request when the preview URL is not already known
+        let mut operations = vec![GqlOperation::new(
+            "VideoMetadata",
+            "226edb3e692509f727fd56821f5653c05740242c82b0388883e0c0e75dcbf687",
+            &VideoMetadataVariables {
+                channel_login: "",
+                video_id: self.id.to_string(),
+            },
+        )?];
+        if self.animated_preview_url.is_empty() {
+            operations.push(GqlOperation::new(
+                "VideoPlayer_VODSeekbarPreviewVideo",
+                "07e99e4d56c5a7c67117a154777b0baf85a5ffefa393b213f4bc712ccaf85dd6",
+                &SeekbarPreviewVariables {
+                    include_private: false,
+                    video_id: self.id.to_string(),
+                },
+            )?);
         }
-        let chunked_index = preview_url
-            .find("storyboards")
-            .unwrap_or_else(|| panic!("\n'storyboards' was not found in the URL"));
+        let mut responses = twitch_gql::send_batch(&operations)?.into_iter();
+        let not_found = || Error::NotFound(format!("VOD {}", self.id));
+        let metadata: VideoData<VideoMetadata> = twitch_gql::data(responses.next().unwrap())?;
+        let vod_type = metadata.video.ok_or_else(not_found)?.broadcast_type;
+        let preview_url = match responses.next() {
+            Some(response) => {
+                let preview: VideoData<SeekbarPreviewVideo> = twitch_gql::data(response)?;
+                preview.video.ok_or_else(not_found)?.seek_previews_url
+            }
+            None => self.animated_preview_url.to_owned(),
+        };
+        let chunked_index = match preview_url.find("storyboards") {
+            Some(chunked_index) => chunked_index,
+            None => {
+                return Err(Error::Schema(format!(
+                    "'storyboards' was not found in {}",
+                    preview_url
+                )))
+            }
+        };
         let domain_url = preview_url[..chunked_index].to_owned() + "chunked/";
-        let request = r#"[
-   {
-      "operationName":"VideoMetadata",
-      "variables":{
-         "channelLogin":"",
-         "videoID":""#
-            .to_owned()
-            + &self.id.to_string()
-            + r#""
-      },
-      "extensions":{
-         "persistedQuery":{
-            "version":1,
-            "sha256Hash":"226edb3e692509f727fd56821f5653c05740242c82b0388883e0c0e75dcbf687"
-         }
-      }
-   }
-]"#;
-        let metadata: Value = CLIENT
-            .post("https://gql.twitch.tv/gql")
-            .header("Client-Id", CLIENT_ID)
-            .body(request)
-            .send()
-            .unwrap()
-            .json()
-            .unwrap();
-        let vod_type = clean_quotes(
-            &metadata
-                .get(0)
-                .unwrap()
-                .get("data")
-                .unwrap()
-                .get("video")
-                .unwrap()
-                .get("broadcastType")
-                .unwrap()
-                .to_string(),
-        );
         let vod_type = vod_type.as_str();
-        match vod_type {
+        Ok(match vod_type {
             "HIGHLIGHT" => format!("{}highlight-{}.m3u8", domain_url, self.id),
             "ARCHIVE" => format!("{}index-dvr.m3u8", domain_url),
             _ => format!("https://twitch.tv/videos/{}", self.id),
+        })
+    }
+}
+
+impl VideoSource for TwitchVOD {
+    type Comments = TwitchComments;
+
+    fn platform(&self) -> Platform {
+        Platform::Twitch
+    }
+
+    fn id(&self) -> String {
+        self.id.to_string()
+    }
+
+    fn title(&self) -> String {
+        self.title.to_owned()
+    }
+
+    fn comments_from(&self, offset: f64) -> TwitchComments {
+        TwitchVOD::comments_from(self, offset as u32)
+    }
+
+    fn comments_at(&self, cursor: PageRequest) -> TwitchComments {
+        TwitchVOD::comments_at(self, cursor)
+    }
+
+    fn playback_url(&self) -> Result<String> {
+        self.m3u8()
+    }
+
+    fn link_at(&self, offset: f64) -> String {
+        vod_link(&self.id.to_string(), offset)
+    }
+}
+
+/// Converts a single comment of `VideoCommentsByOffsetOrCursor` into a `ChatMessage` of the VOD `video_id`
+///
+/// The snake_case comments of Twitch's former v5 API, which chat downloaders still save, are read as well
+pub fn parse_comment(raw: Value, video_id: &str, video_title: &str) -> Result<ChatMessage> {
+    let comment: Comment = serde_json::from_value(raw.to_owned())?;
+    let (user_id, login, display_name) = match comment.commenter {
+        Some(commenter) => (commenter.id, commenter.login, commenter.display_name),
+        None => Default::default(),
+    };
+    let mut body = String::new();
+    let mut emotes = Vec::new();
+    let mut cheered = None;
+    for fragment in comment.message.fragments {
+        if let Some(emote) = fragment.emote {
+            //the text of an emote fragment may carry the spaces around the emote
+            let name = fragment.text.trim_start();
+            emotes.push(Emote {
+                id: emote.emote_id,
+                name: name.trim_end().to_owned(),
+                start: body.len() + fragment.text.len() - name.len(),
+            })
+        }
+        if let Some(cheermote) = fragment.cheermote {
+            cheered = Some(cheered.unwrap_or(0) + cheermote.bits)
         }
+        body.push_str(&fragment.text)
+    }
+    if body.is_empty() {
+        body = comment.message.body.unwrap_or_default()
+    }
+    let bits = match comment.message.bits_spent.or(cheered) {
+        Some(bits) => bits,
+        None => parse_cheers(&body),
+    };
+    let notice = match comment
+        .message
+        .user_notice_params
+        .as_ref()
+        .and_then(|params| params.get("msg-id").or_else(|| params.get("msgID")))
+    {
+        Some(Value::String(notice)) if !notice.is_empty() => Some(notice.to_owned()),
+        _ => parse_notice(&body, &display_name, &login),
+    };
+    let color = comment.message.user_color.filter(|color| !color.is_empty());
+    let badges = comment
+        .message
+        .user_badges
+        .unwrap_or_default()
+        .into_iter()
+        .map(|badge| Badge {
+            name: badge.set_id,
+            version: badge.version,
+        })
+        .collect();
+    Ok(ChatMessage {
+        platform: Platform::Twitch,
+        video_id: video_id.to_owned(),
+        video_title: video_title.to_owned(),
+        offset: comment.content_offset_seconds,
+        user_id,
+        login,
+        display_name,
+        color,
+        badges,
+        emotes,
+        bits,
+        notice,
+        body,
+        raw,
+    })
+}
+
+/// Returns a link to the VOD `id` on Twitch that starts playing at `offset` seconds
+pub fn vod_link(id: &str, offset: f64) -> String {
+    let seconds = offset as u32;
+    format!(
+        "https://www.twitch.tv/videos/{}?t={}h{}m{}s",
+        id,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Returns the bits cheered in `body` with the default `Cheer` cheermote, for comments whose bits the response does not say
+///
+/// Words that merely end in a number, such as `party2023`, cheer nothing
+fn parse_cheers(body: &str) -> u32 {
+    body.split_whitespace()
+        .filter_map(|word| CHEER_MATCHER.captures(word))
+        .filter_map(|captures| captures[1].parse::<u32>().ok())
+        .fold(0, u32::saturating_add)
+}
+
+/// Returns the kind of user notice that `body` is, from the text Twitch posts for it, when the response does not say
+///
+/// The text always starts with the name of the user, so messages that merely quote it are not mistaken for notices.
+/// Only the English text is recognized
+fn parse_notice(body: &str, display_name: &str, login: &str) -> Option<String> {
+    let rest = [display_name, login]
+        .iter()
+        .filter(|name| !name.is_empty())
+        .find_map(|name| {
+            body.get(..name.len())
+                .filter(|start| start.eq_ignore_ascii_case(name))
+                .map(|_| &body[name.len()..])
+        })?;
+    NOTICE_MATCHERS
+        .iter()
+        .find(|(_, matcher)| matcher.is_match(rest))
+        .map(|(notice, _)| notice.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn comment(fragments: Value, message: Value) -> ChatMessage {
+        let mut raw = json!({
+            "commenter": {"id": "1", "login": "alice", "displayName": "Alice"},
+            "contentOffsetSeconds": 1.0,
+            "message": message,
+        });
+        raw["message"]["fragments"] = fragments;
+        parse_comment(raw, "1", "Test VOD").unwrap()
+    }
+
+    fn text(body: &str) -> Value {
+        json!([{ "text": body }])
+    }
+
+    #[test]
+    fn bits_are_read_from_the_response_before_the_body() {
+        let spent = comment(text("Cheer100"), json!({"bitsSpent": 50}));
+        assert_eq!(spent.bits, 50);
+        let cheermotes = json!([
+            {"text": "Kappa10", "cheermote": {"bits": 10}},
+            {"text": " Cheer100 "},
+            {"text": "uni5", "cheermote": {"bits": 5}},
+        ]);
+        assert_eq!(comment(cheermotes, json!({})).bits, 15);
+    }
+
+    #[test]
+    fn cheers_in_the_body_are_counted_when_the_response_has_no_bits() {
+        let cheers = comment(text("cheer100 nice CHEER5 party2023 Cheer"), json!({}));
+        assert_eq!(cheers.bits, 105);
+        assert_eq!(comment(text("cheer100x gg"), json!({})).bits, 0);
+    }
+
+    #[test]
+    fn notices_are_read_from_msg_id_before_the_body() {
+        let params = json!({"userNoticeParams": {"msg-id": "raid"}});
+        let raid = comment(text("Alice subscribed at Tier 1."), params);
+        assert_eq!(raid.notice.as_deref(), Some("raid"));
+        let sub = comment(text("Alice subscribed at Tier 1."), json!({}));
+        assert_eq!(sub.notice.as_deref(), Some("sub"));
+        let quoted = comment(text("bob subscribed at Tier 1."), json!({}));
+        assert_eq!(quoted.notice, None);
     }
 }
@@ -0,0 +1,151 @@
+//! Reads the chat of a Twitch channel from the exchanges recorded in `tests/fixtures/twitch`, without touching the network
+//!
+//! The recordings were made against a stand-in for Twitch GQL, see the README
+use chat_reader::{
+    fixtures::ReplayTransport, http::set_transport, Badge, ChannelSource, ChatMessage, Query,
+    Result, TwitchChannel, TwitchVOD, VideoSource,
+};
+use regex::Regex;
+use std::{path::Path, process::Command};
+
+fn replay() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/twitch");
+    set_transport(ReplayTransport::new(dir).unwrap())
+}
+
+fn comments() -> Vec<ChatMessage> {
+    replay();
+    TwitchVOD::new(1)
+        .unwrap()
+        .comments()
+        .collect::<Result<_>>()
+        .unwrap()
+}
+
+#[test]
+fn vod_is_replayed() {
+    replay();
+    let vod = TwitchVOD::new(1).unwrap();
+    assert_eq!(vod.title(), "Test VOD");
+    assert_eq!(
+        vod.m3u8().unwrap(),
+        "https://cdn.example/abc_test_1/chunked/index-dvr.m3u8"
+    );
+}
+
+#[test]
+fn every_page_of_comments_is_read_in_order() {
+    let comments = comments();
+    assert_eq!(comments.len(), 30);
+    assert!(comments
+        .windows(2)
+        .all(|pair| pair[0].offset < pair[1].offset));
+    assert!(comments.iter().all(|message| message.video_id == "1"));
+    assert_eq!(comments[1].display_name, "Bob");
+    assert_eq!(comments[1].login, "bob");
+    assert_eq!(comments[1].body, "hello number 1, with \"quotes\"");
+}
+
+#[test]
+fn comments_keep_their_colors_badges_and_emotes() {
+    let comments = comments();
+    assert_eq!(comments[1].color.as_deref(), Some("#00FF00"));
+    assert_eq!(comments[2].color, None);
+    assert_eq!(
+        comments[4].badges,
+        vec![Badge {
+            name: "subscriber".to_owned(),
+            version: "12".to_owned()
+        }]
+    );
+    assert_eq!(comments[5].badges[0].name, "moderator");
+    let emote = &comments[7].emotes[0];
+    assert_eq!((emote.id.as_str(), emote.name.as_str()), ("25", "Kappa"));
+    assert_eq!(&comments[7].body[emote.start..], "Kappa");
+}
+
+#[test]
+fn bits_are_counted_from_cheers() {
+    let comments = comments();
+    assert_eq!(comments[11].body, "Cheer100 nice party2023");
+    assert_eq!(comments[11].bits, 100);
+    assert_eq!(
+        comments.iter().map(|message| message.bits).sum::<u32>(),
+        200
+    );
+}
+
+#[test]
+fn user_notices_are_recognized() {
+    let comments = comments();
+    assert_eq!(comments[13].notice.as_deref(), Some("subgift"));
+    assert_eq!(comments[17].notice.as_deref(), Some("resub"));
+    assert_eq!(comments[1].notice, None);
+}
+
+#[test]
+fn channel_lists_its_videos_and_clips() {
+    replay();
+    let channel = TwitchChannel::new("testchannel");
+    let videos: Vec<(String, String)> = channel
+        .videos()
+        .unwrap()
+        .iter()
+        .map(|video| (video.id(), video.title()))
+        .collect();
+    assert_eq!(
+        videos,
+        vec![
+            ("1".to_owned(), "Test VOD".to_owned()),
+            ("2".to_owned(), "Second VOD".to_owned())
+        ]
+    );
+    let clips: Vec<String> = channel
+        .clips()
+        .unwrap()
+        .into_iter()
+        .map(|clip| clip.title)
+        .collect();
+    assert_eq!(clips, vec!["funny clip", "Big Play"]);
+}
+
+#[test]
+fn clips_matching_the_filter_are_printed() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/twitch");
+    let output = Command::new(env!("CARGO_BIN_EXE_chat_reader"))
+        .arg("--replay")
+        .arg(&dir)
+        .args(["twitch", "clips", "testchannel", "--filter", "PLAY"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "[Big Play] https://clips.twitch.tv/y\n"
+    );
+}
+
+#[test]
+fn query_reads_only_what_it_selects() {
+    replay();
+    let vod = TwitchVOD::new(1).unwrap();
+    let mut query = Query::new(Regex::new("(?i)kappa").unwrap());
+    query.max_count = Some(2);
+    query.before_context = 1;
+    let selected: Vec<(String, bool)> = query
+        .select(query.read(&vod))
+        .map(|selected| {
+            let selected = selected.unwrap();
+            (selected.message.body, selected.is_context)
+        })
+        .collect();
+    assert_eq!(
+        selected,
+        vec![
+            ("hello number 6, with \"quotes\"".to_owned(), true),
+            ("hello number 7, with \"quotes\" Kappa".to_owned(), false),
+            ("dave gifted a Tier 1 sub to someone13!".to_owned(), true),
+            ("hello number 14, with \"quotes\" Kappa".to_owned(), false),
+        ]
+    );
+}
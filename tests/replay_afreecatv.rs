@@ -0,0 +1,107 @@
+//! Reads the chat of an AfreecaTV blog from the exchanges recorded in `tests/fixtures/afreecatv`, without touching the network
+//!
+//! The recordings were made against a stand-in for the AfreecaTV APIs, see the README
+use chat_reader::{
+    fixtures::ReplayTransport, http::set_transport, AfreecaVideo, Blog, ChatMessage, Result,
+    VideoSource,
+};
+use std::{path::Path, process::Command};
+
+const VIDEO_URL: &str = "https://vod.afreecatv.com/PLAYER/STATION/12345678";
+
+fn fixtures() -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/afreecatv")
+}
+
+fn replay() {
+    set_transport(ReplayTransport::new(fixtures()).unwrap())
+}
+
+#[test]
+fn video_is_read_from_its_page() {
+    replay();
+    let video = AfreecaVideo::new(VIDEO_URL).unwrap();
+    assert_eq!(video.title, "첫 방송 다시보기");
+    assert_eq!(video.title_no, 12345678);
+    assert_eq!(video.playback_url().unwrap(), VIDEO_URL);
+}
+
+#[test]
+fn chat_of_every_row_is_read_in_order() {
+    replay();
+    let comments: Vec<ChatMessage> = AfreecaVideo::new(VIDEO_URL)
+        .unwrap()
+        .comments()
+        .collect::<Result<_>>()
+        .unwrap();
+    let read: Vec<(f64, &str, &str)> = comments
+        .iter()
+        .map(|message| {
+            (
+                message.offset,
+                message.display_name.as_str(),
+                message.body.as_str(),
+            )
+        })
+        .collect();
+    //the second row starts after the 400 seconds of the first
+    assert_eq!(
+        read,
+        vec![
+            (12.5, "시청자1", "안녕하세요"),
+            (40.0, "Viewer2", "hi <3 & bye"),
+            (310.25, "시청자1", "ㅋㅋㅋㅋ"),
+            (405.0, "Viewer3", "second part"),
+            (520.0, "시청자1", "ㅋㅋ"),
+        ]
+    );
+    assert_eq!(comments[0].login, "viewer1");
+    assert!(comments
+        .iter()
+        .all(|message| message.video_id == "12345678"));
+}
+
+#[test]
+fn reading_from_an_offset_skips_earlier_rows() {
+    replay();
+    let bodies: Vec<String> = AfreecaVideo::new(VIDEO_URL)
+        .unwrap()
+        .comments_from(450)
+        .map(|message| message.unwrap().body)
+        .collect();
+    assert_eq!(bodies, vec!["second part", "ㅋㅋ"]);
+}
+
+#[test]
+fn blog_lists_the_videos_of_every_page() {
+    replay();
+    let videos: Vec<(String, String)> = Blog::new("testblog")
+        .videos()
+        .unwrap()
+        .iter()
+        .map(|video| (video.id(), video.title()))
+        .collect();
+    assert_eq!(
+        videos,
+        vec![
+            ("12345678".to_owned(), "첫 방송 다시보기".to_owned()),
+            ("12345679".to_owned(), "Second broadcast".to_owned()),
+            ("12345680".to_owned(), "Third broadcast".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn chat_of_a_blog_is_printed() {
+    let output = Command::new(env!("CARGO_BIN_EXE_chat_reader"))
+        .arg("--replay")
+        .arg(fixtures())
+        .args(["afreeca", "blog", "testblog", "--filter", "ㅋ"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("[00:05:10][시청자1]: ㅋㅋㅋㅋ\n[00:08:40][시청자1]: ㅋㅋ\n"));
+    assert!(stdout.contains("Third broadcast v12345680"));
+    assert!(!stdout.contains("안녕하세요"));
+}